@@ -0,0 +1,306 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::ops::{Add, Div, Mul, Neg, Rem, Sub};
+use crate::{Definitions, Error, Functions, Operator, SolveOptions};
+use crate::interpret::{undefined_function, undefined_variable, EvalState};
+use crate::lex::Token;
+use crate::postfix::ShuntedStack;
+use crate::value::positive_zero;
+
+/// A node in the expression tree of a parsed expression.
+/// See `parse` for creating one from input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ast {
+    /// A number literal
+    Num(f64),
+    /// A variable identifier
+    Var(String),
+    /// An operator applied to two operands, i.e. `left + right`
+    BinaryOp {
+        /// The operator
+        op: Operator,
+        /// The left hand operand
+        left: Box<Ast>,
+        /// The right hand operand
+        right: Box<Ast>,
+    },
+    /// An operator applied to a single operand, i.e. `-operand`
+    UnaryOp {
+        /// The operator
+        op: Operator,
+        /// The operand
+        operand: Box<Ast>,
+    },
+    /// A function call, i.e. `name(args...)`
+    Call {
+        /// The name of the function
+        name: String,
+        /// The arguments passed to the function
+        args: Vec<Ast>,
+    },
+}
+
+/// Another name for `Ast`, for building expressions in code instead of parsing them.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{Definitions, Expr};
+///
+/// let expr = (Expr::var("x") + 1.0) * 2.0;
+/// assert_eq!(expr.to_string(), "(x + 1) * 2");
+///
+/// let mut defs = Definitions::new();
+/// defs.register("x", 3);
+/// assert_eq!(expr.eval(Some(&defs), None), Ok(8.0));
+/// ```
+pub type Expr = Ast;
+
+impl Ast {
+    /// A number
+    pub fn num(n: f64) -> Ast {
+        Ast::Num(n)
+    }
+
+    /// A variable, which is resolved from the definitions when evaluating
+    pub fn var<S: Into<String>>(name: S) -> Ast {
+        Ast::Var(name.into())
+    }
+
+    /// A call to a function, which is resolved from the functions when evaluating
+    pub fn call<S: Into<String>>(name: S, args: Vec<Ast>) -> Ast {
+        Ast::Call { name: name.into(), args }
+    }
+
+    /// Raises this expression to the power of `exponent`
+    pub fn pow<T: Into<Ast>>(self, exponent: T) -> Ast {
+        Ast::binary(Operator::Pow, self, exponent.into())
+    }
+
+    fn binary(op: Operator, left: Ast, right: Ast) -> Ast {
+        Ast::BinaryOp { op, left: Box::new(left), right: Box::new(right) }
+    }
+
+    /// Evaluates the tree, using the definitions and functions to resolve variables and function calls.
+    pub fn eval(&self, definitions: Option<&Definitions>, functions: Option<&Functions>) -> Result<f64, Error> {
+        self.eval_with_options(definitions, functions, &SolveOptions::default())
+    }
+
+    pub(crate) fn eval_with_options(&self, definitions: Option<&Definitions>, functions: Option<&Functions>, options: &SolveOptions) -> Result<f64, Error> {
+        self.eval_at_depth(definitions, functions, options, 0).map(positive_zero)
+    }
+
+    /// `depth` is the number of function calls this node is nested in
+    pub(crate) fn eval_at_depth(&self, definitions: Option<&Definitions>, functions: Option<&Functions>, options: &SolveOptions, depth: usize) -> Result<f64, Error> {
+        match self {
+            Ast::Num(n) => Ok(*n),
+            Ast::Var(name) => match definitions.and_then(|d| d.get(name)) {
+                Some(value) => Ok(*value),
+                None => Err(undefined_variable(name, functions)),
+            },
+            // the right operand is only evaluated if it decides the result,
+            // so `0 && x` is 0 even if `x` isn't defined
+            Ast::BinaryOp { op: op @ (Operator::And | Operator::Or), left, right } => {
+                let left = left.eval_at_depth(definitions, functions, options, depth)? != 0.0;
+                if left == (*op == Operator::Or) {
+                    return Ok(if left { 1.0 } else { 0.0 });
+                }
+                Ok(if right.eval_at_depth(definitions, functions, options, depth)? != 0.0 { 1.0 } else { 0.0 })
+            }
+            Ast::BinaryOp { op, left, right } => {
+                if op.is_unary() {
+                    return Err(Error::InvalidOperator { op: op.to_string() });
+                }
+                let left = left.eval_at_depth(definitions, functions, options, depth)?;
+                let right = right.eval_at_depth(definitions, functions, options, depth)?;
+                op.apply(left, right, options)
+            }
+            Ast::UnaryOp { op, .. } if !op.is_unary() => Err(Error::InvalidOperator { op: op.to_string() }),
+            Ast::UnaryOp { op, operand } => op.apply_unary(operand.eval_at_depth(definitions, functions, options, depth)?),
+            Ast::Call { name, args } => {
+                if depth >= options.max_depth {
+                    return Err(Error::DepthExceeded);
+                }
+                if !options.allows_function(name) {
+                    return Err(Error::FunctionNotAllowed { name: name.to_string() });
+                }
+                let functions = match functions {
+                    Some(functions) if functions.exists(name) => functions,
+                    _ => return Err(undefined_function(name, definitions)),
+                };
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(arg.eval_at_depth(definitions, Some(functions), options, depth + 1)?);
+                }
+                functions.call(name, values, definitions, depth, &mut EvalState::default())
+            }
+        }
+    }
+
+    fn from_token(token: &Token) -> Result<Ast, Error> {
+        Ok(match token {
+            Token::Num(n) => Ast::Num(n.as_f64()),
+            Token::Identifier(name) => Ast::Var(name.to_string()),
+            Token::Function(name, params) => {
+                let mut args = Vec::new();
+                for param in params {
+                    if let Token::Operator(_) | Token::Symbol(_) = param {
+                        return Err(Error::InvalidArgument { name: name.to_string(), value: param.to_string() });
+                    }
+                    args.push(Ast::from_token(param)?);
+                }
+                Ast::Call { name: name.to_string(), args }
+            }
+            Token::Group(stack) => build_ast(&mut stack.rewound())?,
+            Token::Operator(_) | Token::Symbol(_) => return Err(Error::InvalidOperand { op: token.to_string() }),
+        })
+    }
+
+    /// returns true if this node must be wrapped in parentheses to be an operand of `parent`
+    fn needs_parens(&self, parent: &Operator, is_right: bool) -> bool {
+        match self {
+            // a comma operator is always written with its own parentheses
+            Ast::BinaryOp { op: Operator::Comma, .. } => false,
+            // a comparison directly inside another one would otherwise be read back as a chained comparison
+            Ast::BinaryOp { op, .. } if op.is_comparison() && parent.is_comparison() => true,
+            Ast::BinaryOp { op, .. } => {
+                if op.precedence() != parent.precedence() {
+                    return op.precedence() < parent.precedence();
+                }
+                // a chain of equal precedence operators only needs grouping
+                // on the side the operator does not associate from
+                is_right != parent.is_right_associative()
+            }
+            // a negated negative value would otherwise be read back as a double negation, which cancels out
+            Ast::UnaryOp { op: Operator::Neg, .. } => *parent == Operator::Neg,
+            Ast::Num(n) => *parent == Operator::Neg && n.is_sign_negative(),
+            _ => false,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut Formatter<'_>, parent: &Operator, is_right: bool) -> core::fmt::Result {
+        if self.needs_parens(parent, is_right) {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+/// Formats the tree as an infix expression with consistent spacing,
+/// only adding the parentheses that are required by precedence.
+impl Display for Ast {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Ast::Num(n) => write!(f, "{}", n),
+            Ast::Var(name) => write!(f, "{}", name),
+            Ast::BinaryOp { op: Operator::Comma, left, right } => write!(f, "({}, {})", left, right),
+            Ast::BinaryOp { op, left, right } => {
+                left.fmt_operand(f, op, false)?;
+                write!(f, " {} ", op)?;
+                right.fmt_operand(f, op, true)
+            }
+            Ast::UnaryOp { op: Operator::Abs, operand } => write!(f, "|{}|", operand),
+            Ast::UnaryOp { op: Operator::Percent, operand } => {
+                operand.fmt_operand(f, &Operator::Percent, false)?;
+                write!(f, "%")
+            }
+            Ast::UnaryOp { op, operand } => {
+                write!(f, "{}", op)?;
+                operand.fmt_operand(f, op, true)
+            }
+            Ast::Call { name, args } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl From<f64> for Ast {
+    fn from(n: f64) -> Ast {
+        Ast::Num(n)
+    }
+}
+
+impl<T: Into<Ast>> Add<T> for Ast {
+    type Output = Ast;
+
+    fn add(self, rhs: T) -> Ast {
+        Ast::binary(Operator::Add, self, rhs.into())
+    }
+}
+
+impl<T: Into<Ast>> Sub<T> for Ast {
+    type Output = Ast;
+
+    fn sub(self, rhs: T) -> Ast {
+        Ast::binary(Operator::Sub, self, rhs.into())
+    }
+}
+
+impl<T: Into<Ast>> Mul<T> for Ast {
+    type Output = Ast;
+
+    fn mul(self, rhs: T) -> Ast {
+        Ast::binary(Operator::Mul, self, rhs.into())
+    }
+}
+
+impl<T: Into<Ast>> Div<T> for Ast {
+    type Output = Ast;
+
+    fn div(self, rhs: T) -> Ast {
+        Ast::binary(Operator::Div, self, rhs.into())
+    }
+}
+
+impl<T: Into<Ast>> Rem<T> for Ast {
+    type Output = Ast;
+
+    fn rem(self, rhs: T) -> Ast {
+        Ast::binary(Operator::Mod, self, rhs.into())
+    }
+}
+
+impl Neg for Ast {
+    type Output = Ast;
+
+    fn neg(self) -> Ast {
+        Ast::UnaryOp { op: Operator::Neg, operand: Box::new(self) }
+    }
+}
+
+/// builds the expression tree from the output of the shunting yard algorithm
+pub(crate) fn build_ast(input: &mut ShuntedStack) -> Result<Ast, Error> {
+    let mut nodes: Vec<Ast> = Vec::new();
+    for item in input {
+        if item.is_operand() {
+            nodes.push(Ast::from_token(item.get_operand().unwrap())?);
+            continue;
+        }
+
+        let op = item.get_operator().unwrap().clone();
+        if op.is_unary() {
+            let operand = nodes.pop().ok_or(Error::UnexpectedEOI)?;
+            nodes.push(Ast::UnaryOp { op, operand: Box::new(operand) });
+        } else {
+            let right = nodes.pop().ok_or(Error::UnexpectedEOI)?;
+            let left = nodes.pop().ok_or(Error::UnexpectedEOI)?;
+            nodes.push(Ast::BinaryOp { op, left: Box::new(left), right: Box::new(right) });
+        }
+    }
+
+    if nodes.len() != 1 {
+        return Err(Error::InvalidExpression { reason: format!("Expression resolved to {} values instead of 1", nodes.len()) });
+    }
+    Ok(nodes.pop().unwrap())
+}
@@ -0,0 +1,104 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::{evaluate_program_with_options, Definitions, Error, Expression, Functions, SolveOptions};
+use crate::input_reader::InputReader;
+use crate::lex::{self, Token};
+
+/// A calculator that keeps its definitions and functions between evaluations.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::Calculator;
+///
+/// let mut calc = Calculator::new();
+/// calc.define_function("sq(x) = x * x").unwrap();
+/// assert_eq!(calc.eval("sq(5) + sqrt(16)").unwrap(), 29.0);
+/// ```
+pub struct Calculator<'a> {
+    definitions: Definitions,
+    functions: Functions<'a>,
+    options: SolveOptions,
+}
+
+impl<'a> Calculator<'a> {
+    /// Creates a calculator with no definitions and the default functions (see `Functions::default`).
+    pub fn new() -> Self {
+        Self::with_options(SolveOptions::default())
+    }
+
+    /// Creates a calculator with no definitions and the default functions, which evaluates with the given options.
+    pub fn with_options(options: SolveOptions) -> Self {
+        Self {
+            definitions: Definitions::new(),
+            functions: Functions::default(),
+            options,
+        }
+    }
+
+    /// Evaluates an expression using the calculator's definitions and functions.
+    ///
+    /// Like `evaluate_program`, the input can be several statements separated by `;`,
+    /// and variables can be assigned with `name = expression` or updated with `+=`, `-=`, `*=`, `/=`, `//=`, `%=` and `^=`.
+    /// Assigned variables are kept in the calculator's definitions.
+    pub fn eval<S: Into<String>>(&mut self, input: S) -> Result<f64, Error> {
+        evaluate_program_with_options(input, &mut self.definitions, &self.functions, &self.options)
+    }
+
+    /// Defines a function from a declaration of the form `name(params) = body`, i.e. `f(x, y) = x * y + 1`.
+    ///
+    /// When called, the arguments are bound to the parameter names while evaluating the body,
+    /// which can also use the calculator's definitions and functions.
+    /// Calling it with the wrong number of arguments returns `Error::InvalidArgumentCount`.
+    pub fn define_function<S: Into<String>>(&mut self, declaration: S) -> Result<(), Error> {
+        let declaration = declaration.into();
+        let (signature, body) = match declaration.split_once('=') {
+            Some(parts) => parts,
+            None => return Err(Error::Expected { expected: "=".to_string(), found: declaration }),
+        };
+
+        let mut input = InputReader::new(signature);
+        let tokens = lex::lex(&mut input, true, &self.options)?;
+        let (name, args) = match tokens.as_slice() {
+            [Token::Function(name, args)] => (name, args),
+            _ => return Err(Error::Expected { expected: "name(params)".to_string(), found: signature.trim().to_string() }),
+        };
+
+        let mut params = Vec::new();
+        for arg in args {
+            match arg {
+                Token::Identifier(param) => params.push(param.to_string()),
+                _ => return Err(Error::Expected { expected: "parameter name".to_string(), found: arg.to_string() }),
+            }
+        }
+
+        let body = Expression::compile_with_options(body, &self.options)?;
+        self.functions.register_defined(name.as_str(), params, body);
+        Ok(())
+    }
+
+    /// The calculator's definitions
+    pub fn definitions(&self) -> &Definitions {
+        &self.definitions
+    }
+
+    /// The calculator's definitions, which can be used to register new variables
+    pub fn definitions_mut(&mut self) -> &mut Definitions {
+        &mut self.definitions
+    }
+
+    /// The calculator's functions
+    pub fn functions(&self) -> &Functions<'a> {
+        &self.functions
+    }
+
+    /// The calculator's functions, which can be used to register new functions
+    pub fn functions_mut(&mut self) -> &mut Functions<'a> {
+        &mut self.functions
+    }
+}
+
+impl Default for Calculator<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,111 @@
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use crate::{Error, PublicToken, Value};
+
+/// The step of an evaluation, see `Diagnostics`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Stage {
+    /// Splitting the input into tokens
+    Lexing,
+    /// Ordering the tokens by precedence
+    Parsing,
+    /// Calculating the result
+    Evaluating,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Stage::Lexing => write!(f, "lexing"),
+            Stage::Parsing => write!(f, "parsing"),
+            Stage::Evaluating => write!(f, "evaluating"),
+        }
+    }
+}
+
+/// Details about an evaluation that failed, returned by `evaluate_checked`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostics {
+    /// The error the evaluation failed with
+    pub error: Error,
+    /// The step of the evaluation that failed
+    pub stage: Stage,
+    /// The tokens that were lexed, which stops at the failure point if lexing failed
+    pub tokens: Vec<PublicToken>,
+    /// The byte offset in the input that lexing reached, which is the end of the input unless lexing failed
+    pub position: usize,
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} (while {}, at byte {} after {} tokens)", self.error, self.stage, self.position, self.tokens.len())
+    }
+}
+
+/// A problem during an evaluation that didn't stop it, returned by `evaluate_with_warnings`.
+/// Each one is reported once, whether it happened in the result or in a value calculated on the way to it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Warning {
+    /// An integer too large to be stored in an `f64` (above 2^53) was rounded, either because it is the result
+    /// or because it was used in a floating point operation.
+    PrecisionLoss,
+    /// A value was infinite, i.e. because it is too large for an `f64`.
+    Infinite,
+    /// A value was not a number, i.e. from `0 * 2 ^ 2000`.
+    NotANumber,
+}
+
+impl Warning {
+    /// adds the warning to `warnings` unless it is already there
+    fn add_to(self, warnings: &mut Vec<Warning>) {
+        if !warnings.contains(&self) {
+            warnings.push(self);
+        }
+    }
+
+    /// adds a warning if `value` is an integer that is rounded when it is used as an `f64`
+    pub(crate) fn check_conversion(value: Value, warnings: &mut Vec<Warning>) {
+        if let Value::Int(i) = value {
+            if value.as_f64() as i128 != i {
+                Warning::PrecisionLoss.add_to(warnings);
+            }
+        }
+    }
+
+    /// adds the warnings for an operation on `operands` that resulted in `result`. A value that isn't finite
+    /// is only a warning where it first appears, so `inf + 1` doesn't warn about the `inf` it was given
+    pub(crate) fn check_operation(operands: &[Value], result: Value, warnings: &mut Vec<Warning>) {
+        // integer results are exact, or the operation would have been done with floats
+        if let Value::Float(f) = result {
+            for operand in operands {
+                Warning::check_conversion(*operand, warnings);
+            }
+            if f.is_infinite() && operands.iter().all(|operand| operand.as_f64().is_finite()) {
+                Warning::Infinite.add_to(warnings);
+            }
+            if f.is_nan() && !operands.iter().any(|operand| operand.as_f64().is_nan()) {
+                Warning::NotANumber.add_to(warnings);
+            }
+        }
+    }
+
+    /// adds the warnings for the result of an evaluation
+    pub(crate) fn check(result: Value, warnings: &mut Vec<Warning>) {
+        match result {
+            Value::Int(_) => Warning::check_conversion(result, warnings),
+            Value::Float(f) if f.is_infinite() => Warning::Infinite.add_to(warnings),
+            Value::Float(f) if f.is_nan() => Warning::NotANumber.add_to(warnings),
+            _ => {}
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Warning::PrecisionLoss => write!(f, "A value is too large to be exact and was rounded"),
+            Warning::Infinite => write!(f, "A value is infinite"),
+            Warning::NotANumber => write!(f, "A value is not a number"),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::{Definitions, Error, Functions, SolveOptions};
+use crate::input_reader::InputReader;
+use crate::interpret::{interpret_reusing, substitute, EvalState, Scope, Scratch};
+use crate::lex::{self, Symbol, Token};
+use crate::postfix::{self, Jumps, ShuntedStack};
+
+/// An expression that has been parsed ahead of time, so it can be evaluated
+/// many times (i.e. with different definitions) without parsing it again.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{Definitions, Expression};
+///
+/// let expr = Expression::compile("x * 2").unwrap();
+/// let mut defs = Definitions::new();
+/// for x in 0..3 {
+///     defs.register("x", x);
+///     assert_eq!(expr.eval(Some(&defs), None).unwrap(), x as f64 * 2.0);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Expression {
+    postfix: ShuntedStack,
+    /// found once when compiling, as substituting variables doesn't change them
+    jumps: Jumps,
+    options: SolveOptions,
+    max_paren_depth: usize,
+}
+
+/// the most points `Expression::eval_range` evaluates
+const MAX_RANGE_POINTS: usize = 10_000_000;
+
+/// checks the function calls in a token, including calls nested in its arguments, see `Expression::validate`
+fn validate_calls(token: &Token, functions: &Functions) -> Result<(), Error> {
+    if let Token::Function(name, args) = token {
+        if !functions.exists(name) {
+            return Err(Error::UndefinedFunction { name: name.to_string() });
+        }
+        if let Some(arity) = functions.arity(name) {
+            if args.len() != arity {
+                return Err(Error::arg_count(name.as_str(), arity, args.len()));
+            }
+        }
+        for arg in args {
+            validate_calls(arg, functions)?;
+        }
+    }
+    if let Token::Group(stack) = token {
+        for operand in stack.operands() {
+            validate_calls(operand, functions)?;
+        }
+    }
+    Ok(())
+}
+
+/// Counts of what an expression is made of, see `Expression::stats`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct ExprStats {
+    /// The number of numbers and variables, including those in function arguments
+    pub num_operands: usize,
+    /// The number of operators, including unary operators like `-` and `|x|`
+    pub num_operators: usize,
+    /// The number of function calls, including calls nested in function arguments
+    pub num_functions: usize,
+    /// How deeply the parentheses are nested, not counting the parentheses of function calls
+    pub max_paren_depth: usize,
+}
+
+impl ExprStats {
+    fn count_operand(&mut self, operand: &Token) {
+        match operand {
+            Token::Function(_, args) => {
+                self.num_functions += 1;
+                for arg in args {
+                    self.count_operand(arg);
+                }
+            }
+            Token::Group(stack) => {
+                for x in 0..stack.len() {
+                    match stack.peek_at(x).unwrap().get_operand() {
+                        Some(operand) => self.count_operand(operand),
+                        None => self.num_operators += 1,
+                    }
+                }
+            }
+            Token::Operator(_) | Token::Symbol(_) => self.num_operators += 1,
+            _ => self.num_operands += 1,
+        }
+    }
+}
+
+impl Expression {
+    /// Parses an expression, allowing variables and function calls in it.
+    pub fn compile<S: Into<String>>(input: S) -> Result<Expression, Error> {
+        Expression::compile_with_options(input, &SolveOptions::default())
+    }
+
+    /// Parses an expression like `compile`, using the options when parsing and evaluating it.
+    ///
+    /// With `SolveOptions::fold_constants`, the parts of the expression that don't depend on any
+    /// variables or functions are evaluated now instead of every time the expression is evaluated:
+    /// ```
+    /// use calc_lib::{Error, Expression, SolveOptions};
+    ///
+    /// let options = SolveOptions::builder().fold_constants().build();
+    /// let expr = Expression::compile_with_options("2 * 3 + x", &options).unwrap();
+    /// assert_eq!(expr.debug_postfix(), "6 x +");
+    /// assert_eq!(Expression::compile_with_options("x + 1 / 0", &options).unwrap_err(), Error::DivByZero);
+    /// ```
+    pub fn compile_with_options<S: Into<String>>(input: S, options: &SolveOptions) -> Result<Expression, Error> {
+        let mut input = InputReader::new(input.into());
+        let mut tokens = lex::lex(&mut input, true, options)?;
+
+        let (mut depth, mut max_paren_depth) = (0usize, 0);
+        for token in &tokens {
+            match token {
+                Token::Symbol(Symbol::LeftParen) => {
+                    depth += 1;
+                    max_paren_depth = max_paren_depth.max(depth);
+                }
+                Token::Symbol(Symbol::RightParen) => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        let mut postfix = postfix::shunting_yard(&mut tokens, options)?;
+        if options.fold_constants {
+            postfix = postfix::fold_constants(&postfix, options)?;
+        }
+        let jumps = postfix.jumps();
+        Ok(Expression { postfix, jumps, options: options.clone(), max_paren_depth })
+    }
+
+    /// Counts the operands, operators and function calls in the expression, and how deeply its parentheses are nested.
+    ///
+    /// ```
+    /// use calc_lib::{Expression, ExprStats};
+    ///
+    /// let expr = Expression::compile("max(x, 1) * (2 + 3)").unwrap();
+    /// assert_eq!(expr.stats(), ExprStats { num_operands: 4, num_operators: 2, num_functions: 1, max_paren_depth: 1 });
+    /// ```
+    pub fn stats(&self) -> ExprStats {
+        let mut stats = ExprStats { max_paren_depth: self.max_paren_depth, ..ExprStats::default() };
+        for x in 0..self.postfix.len() {
+            let item = self.postfix.peek_at(x).unwrap();
+            match item.get_operand() {
+                Some(operand) => stats.count_operand(operand),
+                None => stats.num_operators += 1,
+            }
+        }
+        stats
+    }
+
+    /// Returns a copy of the expression that is ready to be evaluated from the start, for giving each thread of a
+    /// parallel evaluation its own copy. `Expression` is `Send` and `Sync`, so copies can be moved to other threads,
+    /// while `Definitions` and `Functions` are given to each evaluation separately.
+    ///
+    /// ```
+    /// use calc_lib::{Definitions, Expression};
+    ///
+    /// let expr = Expression::compile("x ^ 2").unwrap();
+    /// let handles: Vec<_> = (0..4).map(|x| {
+    ///     let expr = expr.clone_for_eval();
+    ///     std::thread::spawn(move || {
+    ///         let mut defs = Definitions::new();
+    ///         defs.register("x", x);
+    ///         expr.eval(Some(&defs), None).unwrap()
+    ///     })
+    /// }).collect();
+    /// let results: Vec<f64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    /// assert_eq!(results, vec![0.0, 1.0, 4.0, 9.0]);
+    /// ```
+    pub fn clone_for_eval(&self) -> Expression {
+        Expression { postfix: self.postfix.rewound(), jumps: self.jumps.clone(), options: self.options.clone(), max_paren_depth: self.max_paren_depth }
+    }
+
+    /// Evaluates the expression with the given definitions and functions.
+    pub fn eval(&self, definitions: Option<&Definitions>, functions: Option<&Functions>) -> Result<f64, Error> {
+        self.eval_at_depth(definitions, functions, 0, &mut EvalState::default())
+    }
+
+    /// Evaluates the expression for each value of `var` from `start` to `end` (inclusive) in increments of `step`,
+    /// returning the pairs of `(var, result)`. The other variables come from `definitions`.
+    ///
+    /// The values go from `start` towards `end` whether `step` is positive or negative, so `start` can be
+    /// greater than `end`. A `step` of 0 (or one that isn't finite) returns `Error::InvalidArgument`, as does a range
+    /// with more than 10,000,000 points.
+    ///
+    /// ```
+    /// use calc_lib::Expression;
+    ///
+    /// let expr = Expression::compile("x ^ 2").unwrap();
+    /// let points = expr.eval_range("x", 0.0, 3.0, 1.0, None, None).unwrap();
+    /// assert_eq!(points, vec![(0.0, 0.0), (1.0, 1.0), (2.0, 4.0), (3.0, 9.0)]);
+    /// ```
+    pub fn eval_range(&self, var: &str, start: f64, end: f64, step: f64, definitions: Option<&Definitions>, functions: Option<&Functions>) -> Result<Vec<(f64, f64)>, Error> {
+        if step == 0.0 || !step.is_finite() || !start.is_finite() || !end.is_finite() {
+            return Err(Error::InvalidArgument { name: "eval_range".to_string(), value: format!("can't go from {} to {} in steps of {}", start, end, step) });
+        }
+        let step = if end < start { -step.abs() } else { step.abs() };
+        // calculating each value from the start keeps rounding errors from adding up,
+        // and the small tolerance keeps `end` from being skipped because of them
+        let steps = (end - start) / step + 1e-9;
+        if steps >= MAX_RANGE_POINTS as f64 {
+            return Err(Error::InvalidArgument { name: "eval_range".to_string(), value: format!("{} to {} in steps of {} is more than {} points", start, end, step, MAX_RANGE_POINTS) });
+        }
+        let count = steps as usize + 1;
+
+        let mut scope = definitions.cloned().unwrap_or_default();
+        scope.register(var, start);
+        let mut points = Vec::with_capacity(count);
+        // the expression isn't changed by evaluating it, so the only thing that changes for each point
+        // is the value of `var`, and the evaluation's buffers are reused for every point
+        let mut scratch = Scratch::default();
+        for i in 0..count {
+            let x = start + step * i as f64;
+            *scope.map.get_mut(var).unwrap() = x;
+            let y = interpret_reusing(&self.postfix, &self.jumps, Scope { definitions: Some(&scope), functions, depth: 0, resolver: None }, &self.options, &mut EvalState::default(), &mut scratch)?;
+            points.push((x, y));
+        }
+        Ok(points)
+    }
+
+    /// Returns true if this expression and `other` have the same result for every one of the `samples` of variables,
+    /// to within a relative difference of 10^-9. Results that aren't finite must be exactly the same, except that two
+    /// NaN results are the same. This is only a check on the given samples, not a proof that the expressions are
+    /// equivalent, so the samples should cover the values where they could differ. No functions are available to
+    /// either expression.
+    ///
+    /// Errors from evaluating either expression are returned. With no samples, nothing is compared and the result is true.
+    ///
+    /// ```
+    /// use calc_lib::{Definitions, Expression};
+    ///
+    /// let mut samples = Vec::new();
+    /// for (a, x, y) in [(1, 2, 3), (-4, 0, 7), (3, 5, -5)] {
+    ///     let mut defs = Definitions::new();
+    ///     defs.register("a", a);
+    ///     defs.register("x", x);
+    ///     defs.register("y", y);
+    ///     samples.push(defs);
+    /// }
+    /// let expanded = Expression::compile("a * x + a * y").unwrap();
+    /// assert_eq!(expanded.probably_equivalent(&Expression::compile("a * (x + y)").unwrap(), &samples), Ok(true));
+    /// assert_eq!(expanded.probably_equivalent(&Expression::compile("a * x + y").unwrap(), &samples), Ok(false));
+    /// ```
+    pub fn probably_equivalent(&self, other: &Expression, samples: &[Definitions]) -> Result<bool, Error> {
+        for sample in samples {
+            let (left, right) = (self.eval(Some(sample), None)?, other.eval(Some(sample), None)?);
+            let same = if left.is_finite() && right.is_finite() {
+                (left - right).abs() <= 1e-9 * left.abs().max(right.abs())
+            } else {
+                left == right || (left.is_nan() && right.is_nan())
+            };
+            if !same {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns true if the expression doesn't use any variables or functions, so it always evaluates to the same value.
+    ///
+    /// ```
+    /// use calc_lib::Expression;
+    ///
+    /// assert!(Expression::compile("2 + 3").unwrap().is_constant());
+    /// assert!(!Expression::compile("x + 1").unwrap().is_constant());
+    /// ```
+    pub fn is_constant(&self) -> bool {
+        (0..self.postfix.len()).all(|x| !matches!(
+            self.postfix.peek_at(x).unwrap().get_operand(),
+            Some(Token::Identifier(_) | Token::Function(_, _))
+        ))
+    }
+
+    /// Checks that every function the expression calls is in `functions`, and is given the number of arguments it
+    /// takes if it only takes a certain number (see `Functions::register_with_arity`), without evaluating anything.
+    ///
+    /// ```
+    /// use calc_lib::{Error, Expression, Functions};
+    ///
+    /// let funcs = Functions::default();
+    /// assert_eq!(Expression::compile("sqrt(x) + max(1, 2, 3)").unwrap().validate(&funcs), Ok(()));
+    /// assert_eq!(Expression::compile("sqrt(1, 2)").unwrap().validate(&funcs), Err(Error::arg_count("sqrt", 1, 2)));
+    /// ```
+    pub fn validate(&self, functions: &Functions) -> Result<(), Error> {
+        for x in 0..self.postfix.len() {
+            if let Some(operand) = self.postfix.peek_at(x).unwrap().get_operand() {
+                validate_calls(operand, functions)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a copy of the expression with the variables that are defined in `definitions` replaced by their values,
+    /// leaving the other variables to be defined when it is evaluated. Nothing else is evaluated.
+    ///
+    /// ```
+    /// use calc_lib::{Definitions, Expression};
+    ///
+    /// let expr = Expression::compile("a * x + b").unwrap();
+    /// let mut defs = Definitions::new();
+    /// defs.register("a", 2);
+    /// defs.register("b", 1);
+    /// let linear = expr.substitute(&defs);
+    /// assert_eq!(linear.debug_postfix(), "2.0 x * 1.0 +");
+    /// ```
+    pub fn substitute(&self, definitions: &Definitions) -> Expression {
+        let mut substituted = self.clone();
+        substitute(&mut substituted.postfix, definitions);
+        substituted
+    }
+
+    /// evaluates the expression as the body of a function, `depth` being the number of function calls it is nested in
+    /// and `state` the state of the evaluation that called it
+    pub(crate) fn eval_at_depth(&self, definitions: Option<&Definitions>, functions: Option<&Functions>, depth: usize, state: &mut EvalState) -> Result<f64, Error> {
+        interpret_reusing(&self.postfix, &self.jumps, Scope { definitions, functions, depth, resolver: None }, &self.options, state, &mut Scratch::default())
+    }
+
+    /// Returns the expression in postfix (reverse polish) notation, which is the order it is evaluated in.
+    /// This is useful for checking how an input was parsed.
+    ///
+    /// ```
+    /// use calc_lib::Expression;
+    ///
+    /// let expr = Expression::compile("2 * (3 + 4)").unwrap();
+    /// assert_eq!(expr.debug_postfix(), "2 3 4 + *");
+    /// ```
+    pub fn debug_postfix(&self) -> String {
+        let mut items = Vec::new();
+        for x in 0..self.postfix.len() {
+            items.push(self.postfix.peek_at(x).unwrap().to_string());
+        }
+        items.join(" ")
+    }
+}
@@ -0,0 +1,109 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::{math, Error};
+
+/// Options for displaying a result with `format_result`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The character placed between each group of three digits before the decimal point,
+    /// i.e. `Some(',')` for `1,000,000`. No grouping is done when this is `None`.
+    pub thousands_separator: Option<char>,
+    /// The number of digits to show after the decimal point, rounding the value if needed.
+    /// When this is `None`, as many digits as are needed are shown.
+    pub decimal_places: Option<usize>,
+    /// The character used as the decimal point.
+    pub decimal_separator: char,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            thousands_separator: None,
+            decimal_places: None,
+            decimal_separator: '.',
+        }
+    }
+}
+
+/// Formats a result for display, i.e. grouping thousands or rounding to a number of decimal places.
+/// Infinite and NaN values are formatted as they are by `Display`.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{format_result, FormatOptions};
+///
+/// let options = FormatOptions { thousands_separator: Some(','), decimal_places: Some(2), ..Default::default() };
+/// assert_eq!(format_result(1234567.5, &options), "1,234,567.50");
+/// ```
+pub fn format_result(value: f64, options: &FormatOptions) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let formatted = match options.decimal_places {
+        Some(places) => format!("{:.*}", places, value),
+        None => value.to_string(),
+    };
+
+    let (sign, unsigned) = match formatted.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", formatted.as_str()),
+    };
+    let (integer, fraction) = match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (unsigned, None),
+    };
+
+    let mut result = String::from(sign);
+    for (i, digit) in integer.chars().enumerate() {
+        // a separator goes before every digit that starts a group of three
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            if let Some(separator) = options.thousands_separator {
+                result.push(separator);
+            }
+        }
+        result.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        result.push(options.decimal_separator);
+        result.push_str(fraction);
+    }
+    result
+}
+
+/// Formats an integer result in another base from 2 to 36, using lowercase letters for digits above 9
+/// and a leading `-` for negative values, i.e. `255` is `ff` in base 16.
+///
+/// Returns `Error::InvalidArgument` if the value isn't an integer (or is too large to be one),
+/// or the base isn't from 2 to 36.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::format_radix;
+///
+/// assert_eq!(format!("0x{}", format_radix(255.0, 16).unwrap()), "0xff");
+/// assert_eq!(format_radix(-5.0, 2).unwrap(), "-101");
+/// ```
+pub fn format_radix(value: f64, radix: u32) -> Result<String, Error> {
+    let invalid = |reason: String| Error::InvalidArgument { name: "format_radix".to_string(), value: reason };
+    if !(2..=36).contains(&radix) {
+        return Err(invalid(format!("base {} is not from 2 to 36", radix)));
+    }
+    if !value.is_finite() || math::trunc(value) != value || value.abs() >= u128::MAX as f64 {
+        return Err(invalid(format!("{} is not an integer", value)));
+    }
+
+    let mut magnitude = value.abs() as u128;
+    let mut digits = Vec::new();
+    loop {
+        digits.push(char::from_digit((magnitude % radix as u128) as u32, radix).unwrap());
+        magnitude /= radix as u128;
+        if magnitude == 0 {
+            break;
+        }
+    }
+    if value < 0.0 {
+        digits.push('-');
+    }
+    Ok(digits.iter().rev().collect())
+}
@@ -1,23 +1,53 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::lex::{PublicToken, TokenKind};
+
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct InputReader {
     stream: Vec<char>,
+    /// the byte offset of the next character in the original input
+    position: usize,
+    /// the tokens lexed so far, if the reader was created with `recording`
+    spans: Option<Vec<PublicToken>>,
 }
 
 impl InputReader {
     pub(crate) fn new<S: Into<String>>(input: S) -> Self {
         Self {
             stream: input.into().chars().collect(),
+            position: 0,
+            spans: None,
         }
     }
 
+    /// creates a reader that keeps track of the kind and span of every token lexed from it
+    pub(crate) fn recording<S: Into<String>>(input: S) -> Self {
+        Self {
+            spans: Some(Vec::new()),
+            ..Self::new(input)
+        }
+    }
+
+    /// reads all of the reader's input into a new input reader
+    #[cfg(feature = "std")]
+    pub(crate) fn from_reader<R: std::io::Read>(mut reader: R) -> std::io::Result<Self> {
+        let mut input = String::new();
+        reader.read_to_string(&mut input)?;
+        Ok(Self::new(input))
+    }
+
     pub(crate) fn consume(&mut self) -> Option<char> {
         if self.stream.is_empty() {
             return None;
         }
-        Some(self.stream.remove(0))
+        let c = self.stream.remove(0);
+        self.position += c.len_utf8();
+        Some(c)
     }
 
+    /// returns the character `n` characters after the next one without consuming anything, or None past the end
+    /// of the input. This is the lookahead for operators made of several characters, like `>=`
     pub(crate) fn peek_at(&self, n: usize) -> Option<char> {
         self.stream.get(n).cloned()
     }
@@ -27,4 +57,18 @@ impl InputReader {
     }
 
     pub(crate) fn is_empty(&self) -> bool { self.stream.is_empty() }
-}
\ No newline at end of file
+
+    pub(crate) fn position(&self) -> usize { self.position }
+
+    /// records a token of the given kind spanning from `start` to the current position
+    pub(crate) fn record(&mut self, kind: TokenKind, start: usize) {
+        let end = self.position;
+        if let Some(spans) = &mut self.spans {
+            spans.push(PublicToken { kind, span: start..end });
+        }
+    }
+
+    pub(crate) fn take_spans(&mut self) -> Vec<PublicToken> {
+        self.spans.take().unwrap_or_default()
+    }
+}
@@ -1,40 +1,138 @@
-use crate::{Definitions, Error, Functions};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::{Definitions, Error, Functions, Operator, SolveOptions, Warning};
+use crate::value::{positive_zero, Value};
 use crate::lex::{Token};
-use crate::postfix::{ShuntedStack, ShuntedStackItem};
+use crate::postfix::{Jumps, ShuntedStack, ShuntedStackItem};
 
-pub(crate) fn interpret(input: &mut ShuntedStack) -> Result<f64, Error> {
+/// counts an operator application or function call towards `options.max_operations`
+fn count_operation(operations: &mut usize, options: &SolveOptions) -> Result<(), Error> {
+    *operations += 1;
+    if *operations > options.max_operations {
+        return Err(Error::OperationLimitExceeded);
+    }
+    Ok(())
+}
+
+pub(crate) fn interpret(input: &mut ShuntedStack, options: &SolveOptions) -> Result<f64, Error> {
+    interpret_value(input, options).map(Value::as_f64)
+}
+
+/// interprets the stack, keeping the result an integer if it is one
+pub(crate) fn interpret_value(input: &mut ShuntedStack, options: &SolveOptions) -> Result<Value, Error> {
+    interpret_with_warnings(input, options).map(|(result, _)| result)
+}
+
+/// interprets the stack like `interpret_value`, along with the warnings for the result and the values calculated for it
+pub(crate) fn interpret_with_warnings(input: &mut ShuntedStack, options: &SolveOptions) -> Result<(Value, Vec<Warning>), Error> {
+    let mut state = EvalState::default();
+    let result = interpret_in(input, &input.jumps(), Scope::default(), options, &mut state, &mut Scratch::default())?;
+    Warning::check(result, &mut state.warnings);
+    Ok((result, state.warnings))
+}
+
+/// returns true if `left` decides the result of `op`, which is a `&&` or `||`, without its right operand
+fn decides(op: &Operator, left: Value) -> bool {
+    (left.as_f64() != 0.0) == (*op == Operator::Or)
+}
+
+/// the buffers an evaluation uses, which are kept so their allocations can be reused when evaluating many times.
+/// They are cleared before they are used
+#[derive(Default)]
+pub(crate) struct Scratch {
+    pub(crate) operand_stack: Vec<Token>,
+    /// the results of the function calls made before evaluating, by the index of the call in the stack
+    pub(crate) calls: Vec<Option<f64>>,
+}
+
+/// interprets the stack like `interpret_value`, resolving its variables and function calls from `scope`
+/// as they are reached, unless the call was already made and its result is in `scratch.calls`.
+/// `jumps` are the stack's jumps, see `ShuntedStack::jumps`.
+pub(crate) fn interpret_in(input: &ShuntedStack, jumps: &Jumps, scope: Scope, options: &SolveOptions, state: &mut EvalState, scratch: &mut Scratch) -> Result<Value, Error> {
     // loop through the stack until an operator is found, pushing the operands onto the operand stack
     // in the process
-    let mut operand_stack = Vec::new();
-    for item in input {
-        if item.is_operand() {
-            operand_stack.push(item.get_operand().unwrap().clone());
+    let Scratch { operand_stack, calls } = scratch;
+    operand_stack.clear();
+    // the right operand of the last chained comparison, which is the left operand of the next one
+    let mut middle = None;
+    let mut x = 0;
+    while let Some(item) = input.peek_at(x) {
+        // the right operand of a `&&` or `||` is skipped if the left one, which was just evaluated, decides the result
+        if let Some(&(_, end)) = jumps.short_circuits.iter().find(|(start, _)| *start == x) {
+            let op = input.peek_at(end).unwrap().get_operator().unwrap();
+            if let Some(&Token::Num(left)) = operand_stack.last() {
+                if decides(op, left) {
+                    count_operation(&mut state.operations, options)?;
+                    // the result is 1 for `||` and 0 for `&&`, and an integer if the left operand is one
+                    let or = *op == Operator::Or;
+                    let result = match left {
+                        Value::Int(_) => Value::Int(or as i128),
+                        Value::Float(_) => Value::Float(if or { 1.0 } else { 0.0 }),
+                    };
+                    operand_stack.pop();
+                    operand_stack.push(Token::Num(result));
+                    x = end + 1;
+                    continue;
+                }
+            }
+        }
+        if let Some(&(_, end)) = jumps.chain_copies.iter().find(|(start, _)| *start == x) {
+            if let Some(value) = middle.take() {
+                operand_stack.push(Token::Num(value));
+                x = end;
+                continue;
+            }
+        }
+        let call = calls.get(x).copied().flatten();
+        x += 1;
+
+        if let Some(value) = call {
+            operand_stack.push(Token::Num(Value::Float(value)));
+        } else if let Some(operand) = item.get_operand() {
+            operand_stack.push(resolve_operand(operand, scope, options, state)?);
         } else {
             let op = item.get_operator().unwrap();
-            if !op.can_apply() {
-                return Err(Error::InvalidOperator { op: op.to_string() });
+            count_operation(&mut state.operations, options)?;
+            if op.is_unary() {
+                let r = match operand_stack.pop().ok_or(Error::UnexpectedEOI)? {
+                    Token::Num(n) => {
+                        let result = op.apply_unary_value(n)?;
+                        Warning::check_operation(&[n], result, &mut state.warnings);
+                        Token::Num(result)
+                    }
+                    operand => return Err(invalid_operand(&operand, options)),
+                };
+                operand_stack.push(r);
+                continue;
             }
-            let operand_1 = operand_stack.pop().unwrap();
-            let operand_2 = operand_stack.pop().unwrap();
+            let operand_1 = operand_stack.pop().ok_or(Error::UnexpectedEOI)?;
+            let operand_2 = operand_stack.pop().ok_or(Error::UnexpectedEOI)?;
             let r = match operand_2 {
                 Token::Num(n1) => {
                     match operand_1 {
                         Token::Num(n2) => {
                             // o1 is of type Number and o2 is of type Number
-                            Token::Num(op.apply(n1.clone(), n2.clone())?)
+                            let result = op.apply_value(n1, n2, options)?;
+                            Warning::check_operation(&[n1, n2], result, &mut state.warnings);
+                            if item.is_chained() {
+                                middle = Some(n2);
+                            }
+                            Token::Num(result)
                         }
-                        _ => return Err(Error::InvalidOperand { op: operand_1.to_string() }),
+                        _ => return Err(invalid_operand(&operand_1, options)),
                     }
                 }
-                _ => return Err(Error::InvalidOperand { op: operand_1.to_string() })
+                _ => return Err(invalid_operand(&operand_2, options))
             };
             operand_stack.push(r);
         }
     }
 
     if operand_stack.len() != 1 {
-        let mut contained = format!("");
-        for x in &operand_stack {
+        let mut contained = String::new();
+        for x in operand_stack.iter() {
             contained.push_str(format!("{}, ", x).as_str());
         }
         return Err(Error::InvalidExpression { reason: format!("Invalid operand stack ending size {} containing: {}",
@@ -43,80 +141,268 @@ pub(crate) fn interpret(input: &mut ShuntedStack) -> Result<f64, Error> {
 
     let result = operand_stack.pop().unwrap();
     match result {
-        Token::Num(n) => Ok(n.clone()),
+        Token::Num(Value::Float(n)) => Ok(Value::Float(positive_zero(n))),
+        Token::Num(n) => Ok(n),
+        Token::Identifier(_) | Token::Function(_, _) if options.strict_identifiers => Err(invalid_operand(&result, options)),
         _ => Err(Error::InvalidExpression { reason: format!("Invalid interpreted value: {}", result) })
     }
 }
 
-pub(crate) fn interpret_fn(ident: &String, args: &Vec<Token>, functions: &Functions, definitions: Option<&Definitions>) -> Result<f64, Error> {
-    let value = functions.get(ident);
-    if value.is_none() {
-        return Err(Error::UndefinedFunction { name: ident.to_string() });
+/// the error for an operand that couldn't be evaluated, which is an undefined identifier in strict mode
+fn invalid_operand(operand: &Token, options: &SolveOptions) -> Error {
+    match operand {
+        Token::Identifier(name) if options.strict_identifiers => Error::UndefinedVariable { name: name.to_string() },
+        Token::Function(name, _) if options.strict_identifiers => Error::UndefinedFunction { name: name.to_string() },
+        _ => Error::InvalidOperand { op: operand.to_string() },
     }
+}
 
-    // replace args with numbers
-    let mut pass_args = Vec::new();
-    for a in args {
-        if let Token::Num(n) = a {
-            pass_args.push(n.clone());
+/// the error for a variable that isn't defined, which names the mistake if it's a function instead
+pub(crate) fn undefined_variable(name: &str, functions: Option<&Functions>) -> Error {
+    match functions {
+        Some(functions) if functions.exists(name) => Error::NotAValue { name: name.to_string() },
+        _ => Error::UndefinedVariable { name: name.to_string() },
+    }
+}
+
+/// the error for a function that isn't defined, which names the mistake if it's a variable instead
+pub(crate) fn undefined_function(name: &str, definitions: Option<&Definitions>) -> Error {
+    match definitions {
+        Some(definitions) if definitions.exists(name) => Error::NotCallable { name: name.to_string() },
+        _ => Error::UndefinedFunction { name: name.to_string() },
+    }
+}
+
+/// interprets the stack using only integer arithmetic, see `evaluate_int`
+pub(crate) fn interpret_int(input: &mut ShuntedStack, options: &SolveOptions) -> Result<i128, Error> {
+    let mut operand_stack: Vec<i128> = Vec::new();
+    for item in input {
+        if let Some(operand) = item.get_operand() {
+            match operand {
+                Token::Num(Value::Int(i)) => operand_stack.push(*i),
+                Token::Num(Value::Float(f)) => return Err(Error::InvalidNumber { found: f.to_string() }),
+                _ => return Err(Error::InvalidOperand { op: operand.to_string() }),
+            }
+            continue;
+        }
+
+        let op = item.get_operator().unwrap();
+        let r = if op.is_unary() {
+            let operand = operand_stack.pop().ok_or(Error::UnexpectedEOI)?;
+            op.apply_unary_int(operand, options)?
         } else {
-            match a {
-                Token::Identifier(s) => {
-                    if definitions.is_some() {
-                        let value = definitions.unwrap().get(s);
-                        if value.is_none() {
-                            return Err(Error::UndefinedVariable { name: s.to_string() });
-                        }
-                        pass_args.push(value.unwrap().clone());
-                    } else {
-                        return Err(Error::InvalidArgument { name: ident.to_string(), value: a.to_string() });
-                    }
-                }
-                Token::Function(i, a) => {
-                    pass_args.push(interpret_fn(i, a, functions, definitions)?);
-                }
-                _ => {
-                    return Err(Error::InvalidArgument { name: ident.to_string(), value: a.to_string() });
-                }
+            let right = operand_stack.pop().ok_or(Error::UnexpectedEOI)?;
+            let left = operand_stack.pop().ok_or(Error::UnexpectedEOI)?;
+            op.apply_int(left, right, options)?
+        };
+        operand_stack.push(r);
+    }
+
+    if operand_stack.len() != 1 {
+        return Err(Error::InvalidExpression { reason: format!("Invalid operand stack ending size {}", operand_stack.len()) });
+    }
+    Ok(operand_stack.pop().unwrap())
+}
+
+/// the state of an evaluation, which is shared by every function call in it
+#[derive(Default)]
+pub(crate) struct EvalState {
+    /// the results of function calls, keyed by the function's name and the bits of its arguments
+    memo: BTreeMap<(String, Vec<u64>), f64>,
+    /// the number of operations done so far, see `SolveOptions::max_operations`
+    operations: usize,
+    /// the warnings about the values calculated so far, see `evaluate_with_warnings`
+    warnings: Vec<Warning>,
+}
+
+/// the variables and functions an evaluation can use, and the number of function calls it is nested in
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Scope<'a, 'f> {
+    pub(crate) definitions: Option<&'a Definitions>,
+    pub(crate) functions: Option<&'a Functions<'f>>,
+    pub(crate) depth: usize,
+    /// looks up the variables that aren't in the definitions as they are reached, see `evaluate_with_resolver`
+    pub(crate) resolver: Option<&'a Resolver<'a>>,
+}
+
+/// looks up the value of a variable that isn't defined, see `Scope::resolver`
+pub(crate) type Resolver<'a> = dyn Fn(&str) -> Option<f64> + 'a;
+
+impl Scope<'_, '_> {
+    /// the value of a variable from the definitions, or from the resolver if it isn't defined
+    fn lookup(&self, name: &str) -> Option<f64> {
+        self.definitions.and_then(|d| d.get(name)).copied().or_else(|| self.resolver.and_then(|resolver| resolver(name)))
+    }
+
+    /// the scope of the arguments of a function call made in this scope
+    fn nested(self) -> Self {
+        Scope { depth: self.depth + 1, ..self }
+    }
+}
+
+/// checks that an operand can be resolved from the scope before anything is evaluated. A variable that isn't
+/// defined is an error when there are definitions, and a function used as a variable (or the other way around)
+/// is an error when only the one it's used as is missing. With a resolver, variables that aren't defined are
+/// only checked once they are looked up
+fn check_operand(operand: &Token, scope: Scope) -> Result<(), Error> {
+    match operand {
+        Token::Identifier(_) if scope.resolver.is_some() => Ok(()),
+        Token::Identifier(ident) if scope.definitions.is_some_and(|d| !d.exists(ident.as_str())) => {
+            Err(undefined_variable(ident, scope.functions))
+        }
+        Token::Identifier(ident) if scope.definitions.is_none() && scope.functions.is_some_and(|f| f.exists(ident)) => {
+            Err(Error::NotAValue { name: ident.to_string() })
+        }
+        Token::Function(ident, _) if scope.functions.is_none() && scope.definitions.is_some_and(|d| d.exists(ident.as_str())) => {
+            Err(Error::NotCallable { name: ident.to_string() })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// resolves an operand to a number if the scope has what it needs, calling it if it's a function.
+/// Operands that can't be resolved are left as they are, and are an error once an operator is applied to them
+fn resolve_operand(operand: &Token, scope: Scope, options: &SolveOptions, state: &mut EvalState) -> Result<Token, Error> {
+    check_operand(operand, scope)?;
+    match operand {
+        Token::Identifier(ident) => match scope.lookup(ident) {
+            Some(value) => Ok(Token::Num(Value::Float(value))),
+            None if scope.resolver.is_some() => Err(undefined_variable(ident, scope.functions)),
+            None => Ok(operand.clone()),
+        },
+        Token::Function(ident, args) => match scope.functions {
+            Some(functions) => {
+                let value = interpret_fn(ident, args, functions, scope, options, state)?;
+                Ok(Token::Num(Value::Float(value)))
             }
+            None => Ok(operand.clone()),
+        },
+        _ => Ok(operand.clone()),
+    }
+}
+
+/// resolves an argument of the function `ident`, which is called in `scope`, to a number. An identifier is looked up
+/// in the definitions (and the resolver) first, and a function call is evaluated. Anything else is an error:
+/// - the name of a function that isn't called is `Error::NotAValue`
+/// - a variable that isn't defined is `Error::UndefinedVariable` when there are definitions or a resolver to look it up
+///   in (or with `SolveOptions::strict_identifiers`), and `Error::InvalidArgument` when there aren't
+fn resolve_arg(ident: &str, arg: &Token, functions: &Functions, scope: Scope, options: &SolveOptions, state: &mut EvalState) -> Result<f64, Error> {
+    match arg {
+        Token::Num(n) => {
+            Warning::check_conversion(*n, &mut state.warnings);
+            Ok(n.as_f64())
         }
+        Token::Identifier(name) => match scope.lookup(name) {
+            Some(value) => Ok(value),
+            None if functions.exists(name) => Err(Error::NotAValue { name: name.to_string() }),
+            None if scope.definitions.is_some() || scope.resolver.is_some() || options.strict_identifiers => {
+                Err(Error::UndefinedVariable { name: name.to_string() })
+            }
+            None => Err(Error::InvalidArgument { name: ident.to_string(), value: arg.to_string() }),
+        },
+        Token::Function(name, args) => interpret_fn(name, args, functions, scope.nested(), options, state),
+        Token::Group(stack) => {
+            let scope = Scope { functions: Some(functions), ..scope.nested() };
+            let value = interpret_in(stack, &stack.jumps(), scope, options, state, &mut Scratch::default())?;
+            Warning::check_conversion(value, &mut state.warnings);
+            Ok(value.as_f64())
+        }
+        Token::Operator(_) | Token::Symbol(_) => Err(Error::InvalidArgument { name: ident.to_string(), value: arg.to_string() }),
     }
+}
 
-    value.unwrap()(pass_args)
+/// calls a function from `scope`, whose depth is the number of function calls it is nested in.
+/// When `options.memoize` is set, results are stored in and reused from the memo in `state`.
+pub(crate) fn interpret_fn(ident: &String, args: &Vec<Token>, functions: &Functions, scope: Scope, options: &SolveOptions, state: &mut EvalState) -> Result<f64, Error> {
+    let Scope { definitions, depth, .. } = scope;
+    if depth >= options.max_depth {
+        return Err(Error::DepthExceeded);
+    }
+    count_operation(&mut state.operations, options)?;
+    if !options.allows_function(ident) {
+        return Err(Error::FunctionNotAllowed { name: ident.to_string() });
+    }
+    if !functions.exists(ident) {
+        return Err(undefined_function(ident, definitions));
+    }
+
+    let mut pass_args = Vec::new();
+    for arg in args {
+        pass_args.push(resolve_arg(ident, arg, functions, scope, options, state)?);
+    }
+
+    if !options.memoize {
+        return functions.call(ident, pass_args, definitions, depth, state);
+    }
+    let key = (ident.to_string(), pass_args.iter().map(|arg| arg.to_bits()).collect());
+    if let Some(result) = state.memo.get(&key) {
+        return Ok(*result);
+    }
+    let result = functions.call(ident, pass_args, definitions, depth, state)?;
+    state.memo.insert(key, result);
+    Ok(result)
 }
 
-pub(crate) fn interpret_with_definitions(input: &mut ShuntedStack, definitions: Option<&Definitions>, functions: Option<&Functions>) -> Result<f64, Error> {
-    if definitions.is_some() {
-        let definitions = definitions.unwrap();
-        for x in 0..input.len() {
-            let item = input.peek_at(x).unwrap();
-            if item.is_operand() {
-                let operand = item.get_operand().unwrap();
-                match operand {
-                    Token::Identifier(ident) => {
-                        let value = definitions.get(ident);
-                        if value.is_none() {
-                            return Err(Error::UndefinedVariable { name: ident.to_string() });
-                        }
-                        input.replace(x, ShuntedStackItem::new_operand(Token::Num(value.unwrap().clone())));
-                    }
-                    _ => {}
-                }
+/// replaces the variables that are defined with their values, including those in function arguments,
+/// returning true if any were replaced. Variables that aren't defined are left as they are.
+pub(crate) fn substitute(input: &mut ShuntedStack, definitions: &Definitions) -> bool {
+    let mut substituted_any = false;
+    for x in 0..input.len() {
+        let item = input.peek_at(x).unwrap();
+        if let Some(substituted) = item.get_operand().and_then(|operand| substitute_token(operand, definitions)) {
+            input.replace(x, ShuntedStackItem::new_operand(substituted));
+            substituted_any = true;
+        }
+    }
+    substituted_any
+}
+
+/// the token with its defined variables replaced, or None if it has none
+fn substitute_token(token: &Token, definitions: &Definitions) -> Option<Token> {
+    match token {
+        Token::Identifier(ident) => definitions.get(ident).map(|value| Token::Num(Value::Float(*value))),
+        Token::Function(ident, args) => {
+            let substituted: Vec<Option<Token>> = args.iter().map(|arg| substitute_token(arg, definitions)).collect();
+            if substituted.iter().all(Option::is_none) {
+                return None;
             }
+            let args = args.iter().zip(substituted).map(|(arg, substituted)| substituted.unwrap_or_else(|| arg.clone())).collect();
+            Some(Token::Function(ident.to_string(), args))
+        }
+        Token::Group(stack) => {
+            let mut stack = stack.clone();
+            substitute(&mut stack, definitions).then_some(Token::Group(stack))
         }
+        _ => None,
     }
-    if functions.is_some() {
-        let functions = functions.unwrap();
-        for x in 0..input.len() {
-            let item = input.peek_at(x).unwrap();
-            if item.is_operand() {
-                let operand = item.get_operand().unwrap();
-                if let Token::Function(ident, args) = operand {
-                    let val = interpret_fn(ident, args, functions, definitions)?;
-                    input.replace(x, ShuntedStackItem::new_operand(Token::Num(val)));
-                }
+}
+
+/// interprets the stack with the definitions and functions, see `interpret_reusing`
+pub(crate) fn interpret_with_definitions(input: &ShuntedStack, definitions: Option<&Definitions>, functions: Option<&Functions>, options: &SolveOptions, depth: usize) -> Result<f64, Error> {
+    let scope = Scope { definitions, functions, depth, resolver: None };
+    interpret_reusing(input, &input.jumps(), scope, options, &mut EvalState::default(), &mut Scratch::default())
+}
+
+/// interprets the stack with the definitions and functions, without changing it so it can be evaluated again.
+/// `jumps` are the stack's jumps and `scratch` holds the buffers, so neither is made again for each evaluation.
+/// `state` is the state of the evaluation this is part of, which is shared with the bodies of defined functions
+pub(crate) fn interpret_reusing(input: &ShuntedStack, jumps: &Jumps, scope: Scope, options: &SolveOptions, state: &mut EvalState, scratch: &mut Scratch) -> Result<f64, Error> {
+    // the right operands of `&&` and `||` are resolved once they are reached, as they might not be,
+    // while every other name is checked before anything is evaluated and every other call is made first
+    let unconditional = || jumps.conditional.iter().enumerate().filter(|(_, conditional)| !**conditional).map(|(x, _)| x);
+    for x in unconditional() {
+        if let Some(operand) = input.peek_at(x).unwrap().get_operand() {
+            check_operand(operand, scope)?;
+        }
+    }
+    scratch.calls.clear();
+    scratch.calls.resize(input.len(), None);
+    if let Some(functions) = scope.functions {
+        for x in unconditional() {
+            if let Some(Token::Function(ident, args)) = input.peek_at(x).unwrap().get_operand() {
+                scratch.calls[x] = Some(interpret_fn(ident, args, functions, scope, options, state)?);
             }
         }
     }
-    interpret(input)
-}
\ No newline at end of file
+    interpret_in(input, jumps, scope, options, state, scratch).map(Value::as_f64)
+}
@@ -1,124 +1,377 @@
-use std::fmt::{Display, Formatter};
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use core::ops::Range;
 use crate::input_reader::InputReader;
-use crate::Error;
+use crate::{Error, SolveOptions};
 use crate::operator::Operator;
+use crate::postfix::{self, ShuntedStack};
+use crate::value::Value;
+
+/// The kind of a token returned by `tokenize`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A number literal
+    Number,
+    /// A variable identifier
+    Identifier,
+    /// The name of a function being called
+    FunctionName,
+    /// An operator, not including parentheses
+    Operator(Operator),
+    /// An opening or closing parenthesis, or an absolute value bar
+    Paren,
+    /// The separator between function arguments
+    Separator,
+    /// The `=` of an assignment, or the operator and `=` of a compound assignment like `+=`, see `evaluate_program`
+    Assign,
+}
+
+/// A token of an expression and its location, see `tokenize`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicToken {
+    /// What the token is
+    pub kind: TokenKind,
+    /// The byte range of the token in the input
+    pub span: Range<usize>,
+}
+
+/// a symbol that only the parser handles, which is never applied to operands like an `Operator`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Symbol {
+    LeftParen,                // (
+    RightParen,               // )
+    Bar,                      // | surrounding an absolute value
+    Assign,                   // =
+    CompoundAssign(Operator), // an operator followed by =, i.e. +=
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
+        match self {
+            Symbol::LeftParen => write!(f, "("),
+            Symbol::RightParen => write!(f, ")"),
+            Symbol::Bar => write!(f, "|"),
+            Symbol::Assign => write!(f, "="),
+            Symbol::CompoundAssign(op) => write!(f, "{}=", op),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub(crate) enum Token {
     Operator(Operator),
+    Symbol(Symbol),
     Identifier(String),
-    Num(f64),
+    Num(Value),
     Function(String, Vec<Token>),
+    /// an expression passed as a function argument, which is parsed when it is lexed, see `lex_argument`
+    Group(ShuntedStack),
 }
 
 impl Display for Token {
-    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter) -> core::fmt::Result {
         match self {
             Token::Operator(o) => write!(f, "{}", o),
+            Token::Symbol(s) => write!(f, "{}", s),
             Token::Identifier(ref s) => write!(f, "{}", s),
             Token::Num(n) => write!(f, "{}", n),
             Token::Function(s, _) => write!(f, "{}(...)", s),
+            Token::Group(_) => write!(f, "(...)"),
         }
     }
 }
 
-fn lex_ident(input: &mut InputReader, allow_idents: bool) -> Result<Token, Error> {
+/// the operator written as a word, such as `mod`, which can't be used as the name of a variable or function
+fn keyword(ident: &str) -> Option<Operator> {
+    match ident {
+        "mod" => Some(Operator::Mod),
+        "div" => Some(Operator::IntDiv),
+        _ => None,
+    }
+}
+
+fn lex_ident(input: &mut InputReader, allow_idents: bool, options: &SolveOptions, depth: usize) -> Result<Token, Error> {
+    let start = input.position();
     let mut ident = String::new();
     while let Some(c) = input.peek() {
-        if c.is_alphanumeric() {
+        if c.is_alphanumeric() || c == '_' {
             ident.push(c);
             input.consume();
-        } else if c == '(' {
+        } else if c == '(' && keyword(&ident).is_none() {
+            if depth >= options.max_depth {
+                return Err(Error::DepthExceeded);
+            }
+            input.record(TokenKind::FunctionName, start);
+            let paren = input.position();
             input.consume();
+            input.record(TokenKind::Paren, paren);
+            let separator = if options.decimal_comma { ';' } else { ',' };
             let mut params = Vec::new();
+            // every separator must be followed by an argument, so trailing
+            // separators (`f(1,)`) and empty arguments (`f(1,,2)`) are rejected
+            let mut after_separator = false;
+            let mut closed = false;
             while let Some(c) = input.peek() {
                 if c == ' ' || c == '\n' || c == '\t' || c == '\r' {
                     input.consume();
                     continue;
                 }
+                if (c == ')' && after_separator) || c == separator {
+                    return Err(Error::Expected { expected: "argument".to_string(), found: c.to_string() });
+                }
                 if c == ')' {
+                    let paren = input.position();
                     input.consume();
+                    input.record(TokenKind::Paren, paren);
+                    closed = true;
                     break;
                 }
 
-                params.push(next_token(input, allow_idents)?);
+                params.push(lex_argument(input, allow_idents, options, depth + 1)?);
+                after_separator = false;
                 while let Some(c2) = input.peek() {
-                    if c2 == ' ' || c == '\n' || c == '\t' || c == '\r' {
+                    if c2 == ' ' || c2 == '\n' || c2 == '\t' || c2 == '\r' {
                         input.consume();
                         continue;
                     }
                     if c2 == ')' {
                         break;
                     }
-                    if c2 == ',' {
+                    if c2 == separator {
+                        let separator = input.position();
                         input.consume();
+                        input.record(TokenKind::Separator, separator);
+                        after_separator = true;
                         break;
                     } else {
-                        return Err(Error::Expected { expected: ", or )".to_string(), found: c2.to_string()});
+                        return Err(Error::Expected { expected: format!("{} or )", separator), found: c2.to_string()});
                     }
                 }
             }
 
+            // the input ended before the arguments did, i.e. `sqrt(4`
+            if !closed {
+                return Err(Error::MismatchedParentheses { found: '(', missing: ')' });
+            }
             return Ok(Token::Function(ident, params));
         } else {
             break;
         }
     }
+    // operators are recorded by `next_token`
+    if let Some(op) = keyword(&ident) {
+        return Ok(Token::Operator(op));
+    }
+    input.record(TokenKind::Identifier, start);
     Ok(Token::Identifier(ident))
 }
 
-fn lex_number(input: &mut InputReader) -> Result<Token, Error> {
+/// lexes a function argument, which can be any expression, up to the separator or ')' after it.
+/// An argument that is a single operand (i.e. `-2` or `x`) is returned as it is, and anything else as a `Token::Group`
+fn lex_argument(input: &mut InputReader, allow_idents: bool, options: &SolveOptions, depth: usize) -> Result<Token, Error> {
+    let separator = if options.decimal_comma { ';' } else { ',' };
+    let mut tokens = lex_tokens(input, allow_idents, options, &|_| false, depth, Some(separator))?;
+    let stack = postfix::shunting_yard(&mut tokens, options)?;
+    let operand = match stack.operands().next() {
+        Some(operand) if stack.len() == 1 => Some(operand.clone()),
+        _ => None,
+    };
+    Ok(operand.unwrap_or(Token::Group(stack)))
+}
+
+/// parses the text of a number, keeping the text in the error, which the standard library's parse errors don't
+fn parse_float(number: &str) -> Result<f64, Error> {
+    number.parse().map_err(|_| Error::InvalidNumber { found: number.to_string() })
+}
+
+/// lexes an integer written in another base with a prefix, `0x` (hexadecimal), `0b` (binary) or `0o` (octal).
+/// Every letter and digit after the prefix is part of the literal, so `0xG` is reported as a whole
+/// rather than as `0` followed by `xG`
+fn lex_radix_literal(input: &mut InputReader, radix: u32) -> Result<Token, Error> {
+    let start = input.position();
+    let mut literal = String::new();
+    // the '0' and the prefix
+    for _ in 0..2 {
+        literal.push(input.consume().unwrap());
+    }
+    while let Some(c) = input.peek() {
+        if !c.is_alphanumeric() {
+            break;
+        }
+        literal.push(c);
+        input.consume();
+    }
+    input.record(TokenKind::Number, start);
+    match i128::from_str_radix(&literal[2..], radix) {
+        Ok(n) if literal.len() > 2 => Ok(Token::Num(Value::Int(n))),
+        _ => Err(Error::InvalidNumber { found: literal }),
+    }
+}
+
+fn lex_number(input: &mut InputReader, options: &SolveOptions) -> Result<Token, Error> {
+    if input.peek() == Some('0') {
+        match input.peek_at(1) {
+            Some('x' | 'X') => return lex_radix_literal(input, 16),
+            Some('b' | 'B') => return lex_radix_literal(input, 2),
+            Some('o' | 'O') => return lex_radix_literal(input, 8),
+            _ => {}
+        }
+    }
+    let start = input.position();
+    let decimal_point = if options.decimal_comma { ',' } else { '.' };
     let mut number = String::new();
     let mut decimal = false;
     while let Some(c) = input.peek() {
         if c.is_numeric() {
             number.push(c);
             input.consume();
-        } else if c == '.' {
+        } else if c == decimal_point {
             if decimal {
                 return Err(Error::InvalidNumber { found: number });
             }
             decimal = true;
-            number.push(c);
+            // always parse with a '.' as the decimal point
+            number.push('.');
             input.consume();
         } else {
             break;
         }
     }
-    if decimal {
-        let f = number.parse::<f64>();
-        if f.is_err() {
-            return Err(Error::InvalidNumber { found: number });
+    if !decimal && number.len() <= 3 {
+        if let Some(grouped) = digit_groups(input, &number) {
+            return Err(Error::SpaceInNumber { number: grouped });
         }
-        Ok(Token::Num(f.unwrap()))
+    }
+    let mut value = if decimal {
+        Value::Float(parse_float(&number)?)
     } else {
-        let n = number.parse::<i128>();
-        if n.is_err() {
-            return Err(Error::InvalidNumber { found: number });
+        match number.parse::<i128>() {
+            Ok(n) => Value::Int(n),
+            // integers too large for an i128 are approximated
+            Err(_) => Value::Float(parse_float(&number)?),
+        }
+    };
+    if options.si_suffixes {
+        if let Some(suffixed) = lex_si_suffix(input, value) {
+            value = suffixed;
+        }
+    }
+    if options.binary_suffixes {
+        if let Some(suffixed) = lex_binary_suffix(input, value) {
+            value = suffixed;
+        }
+    }
+    input.record(TokenKind::Number, start);
+    Ok(Token::Num(value))
+}
+
+/// the number written with spaces between groups of three digits, if the digits after `number` are grouped like that.
+/// Two numbers in a row are never valid, so this only changes the error that is reported for them
+fn digit_groups(input: &InputReader, number: &str) -> Option<String> {
+    let is_digit = |n: usize| input.peek_at(n).is_some_and(|c| c.is_ascii_digit());
+    let mut grouped = number.to_string();
+    let mut n = 0;
+    while input.peek_at(n) == Some(' ') && (n + 1..=n + 3).all(is_digit) && !is_digit(n + 4) {
+        grouped.push(' ');
+        for x in 1..=3 {
+            grouped.push(input.peek_at(n + x).unwrap());
         }
-        Ok(Token::Num(n.unwrap() as f64))
+        n += 4;
+    }
+    if n == 0 {
+        return None;
+    }
+    Some(grouped)
+}
+
+/// consumes an SI suffix after a number, returning the number multiplied by it.
+/// Letters after the suffix make it part of an identifier instead, see `SolveOptions::si_suffixes`
+fn lex_si_suffix(input: &mut InputReader, value: Value) -> Option<Value> {
+    let suffix = input.peek()?;
+    if input.peek_at(1).is_some_and(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    let scaled = match suffix {
+        'k' => value.scale(1_000),
+        'M' => value.scale(1_000_000),
+        'G' => value.scale(1_000_000_000),
+        'T' => value.scale(1_000_000_000_000),
+        // dividing keeps exact results like `500m` being 0.5, which multiplying by 0.001 wouldn't
+        'm' => Value::Float(value.as_f64() / 1e3),
+        'u' => Value::Float(value.as_f64() / 1e6),
+        _ => return None,
+    };
+    input.consume();
+    Some(scaled)
+}
+
+/// consumes a binary suffix after a number like `lex_si_suffix`, see `SolveOptions::binary_suffixes`
+fn lex_binary_suffix(input: &mut InputReader, value: Value) -> Option<Value> {
+    if input.peek_at(1) != Some('i') || input.peek_at(2).is_some_and(|c| c.is_alphanumeric() || c == '_') {
+        return None;
     }
+    let power = match input.peek()? {
+        'K' => 1,
+        'M' => 2,
+        'G' => 3,
+        'T' => 4,
+        _ => return None,
+    };
+    input.consume();
+    input.consume();
+    Some(value.scale(1 << (10 * power)))
 }
 
-pub(crate) fn next_token(input: &mut InputReader, allow_idents: bool) -> Result<Token, Error> {
+/// the value of `inf` or `nan` at the start of the input, unless it is part of a longer name or called as a function.
+/// See `SolveOptions::allow_special_floats`
+fn special_float(input: &InputReader) -> Option<f64> {
+    let word: String = (0..3).filter_map(|n| input.peek_at(n)).collect();
+    if input.peek_at(3).is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '(') {
+        return None;
+    }
+    match word.as_str() {
+        "inf" => Some(f64::INFINITY),
+        "nan" => Some(f64::NAN),
+        _ => None,
+    }
+}
+
+/// lexes the next token, `depth` being the number of function calls it is an argument of
+pub(crate) fn next_token(input: &mut InputReader, allow_idents: bool, options: &SolveOptions, depth: usize) -> Result<Token, Error> {
     let next = input.peek();
     if next.is_none() {
         return Err(Error::UnexpectedEOI);
     }
     let c = next.unwrap();
-    Ok(match input.peek().unwrap() {
+    let start = input.position();
+    let token = match input.peek().unwrap() {
         '+' => {
             input.consume();
             Token::Operator(Operator::Add)
         }
-        '-' => {
+        '-' | '−' => {
             input.consume();
             Token::Operator(Operator::Sub)
         }
-        '*' => {
+        // `**` is another way of writing `^`
+        '*' if input.peek_at(1) == Some('*') => {
+            input.consume();
+            input.consume();
+            Token::Operator(Operator::Pow)
+        }
+        '*' | '×' | '·' => {
             input.consume();
             Token::Operator(Operator::Mul)
         }
+        '/' if input.peek_at(1) == Some('/') => {
+            input.consume();
+            input.consume();
+            Token::Operator(Operator::IntDiv)
+        }
         '/' | '÷' => {
             input.consume();
             Token::Operator(Operator::Div)
@@ -131,40 +384,216 @@ pub(crate) fn next_token(input: &mut InputReader, allow_idents: bool) -> Result<
             input.consume();
             Token::Operator(Operator::Pow)
         }
+        '=' if input.peek_at(1) == Some('=') => {
+            input.consume();
+            input.consume();
+            Token::Operator(Operator::Eq)
+        }
         '=' => {
             input.consume();
-            Token::Operator(Operator::Assign)
+            Token::Symbol(Symbol::Assign)
+        }
+        '!' if input.peek_at(1) == Some('=') => {
+            input.consume();
+            input.consume();
+            Token::Operator(Operator::NotEq)
+        }
+        '!' => {
+            input.consume();
+            Token::Operator(Operator::Not)
+        }
+        '&' if input.peek_at(1) == Some('&') => {
+            input.consume();
+            input.consume();
+            Token::Operator(Operator::And)
+        }
+        '≠' => {
+            input.consume();
+            Token::Operator(Operator::NotEq)
+        }
+        '<' if input.peek_at(1) == Some('=') => {
+            input.consume();
+            input.consume();
+            Token::Operator(Operator::LessEq)
+        }
+        '≤' => {
+            input.consume();
+            Token::Operator(Operator::LessEq)
+        }
+        '<' => {
+            input.consume();
+            Token::Operator(Operator::Less)
+        }
+        '>' if input.peek_at(1) == Some('=') => {
+            input.consume();
+            input.consume();
+            Token::Operator(Operator::GreaterEq)
+        }
+        // function arguments are lexed by `lex_ident`, so this is a comma operator
+        c if c == if options.decimal_comma { ';' } else { ',' } => {
+            input.consume();
+            Token::Operator(Operator::Comma)
+        }
+        '≥' => {
+            input.consume();
+            Token::Operator(Operator::GreaterEq)
+        }
+        '>' => {
+            input.consume();
+            Token::Operator(Operator::Greater)
         }
         '(' => {
             input.consume();
-            Token::Operator(Operator::LeftParen)
+            Token::Symbol(Symbol::LeftParen)
+        }
+        '|' => {
+            input.consume();
+            Token::Symbol(Symbol::Bar)
         }
         ')' => {
             input.consume();
-            Token::Operator(Operator::RightParen)
+            Token::Symbol(Symbol::RightParen)
+        }
+        _ if options.allow_special_floats && special_float(input).is_some() => {
+            let value = special_float(input).unwrap();
+            for _ in 0..3 {
+                input.consume();
+            }
+            input.record(TokenKind::Number, start);
+            Token::Num(Value::Float(value))
         }
-        _ if (c.is_alphabetic() || c == '_') && allow_idents => lex_ident(input, allow_idents)?,
-        _ if c.is_numeric() => lex_number(input)?,
+        _ if (c.is_alphabetic() || c == '_') && allow_idents => lex_ident(input, allow_idents, options, depth)?,
+        _ if c.is_numeric() => lex_number(input, options)?,
         _ => {
             return Err(Error::InvalidCharacter { c });
         }
-    })
+    };
+    // an arithmetic operator written right before a '=' is a compound assignment, see `evaluate_program`
+    let token = match token {
+        Token::Operator(op @ (Operator::Add | Operator::Sub | Operator::Mul | Operator::Div | Operator::IntDiv | Operator::Mod | Operator::Pow))
+            if input.peek() == Some('=') => {
+            input.consume();
+            Token::Symbol(Symbol::CompoundAssign(op))
+        }
+        token => token,
+    };
+    // numbers and identifiers record themselves, as a function call is made of several spans
+    match &token {
+        Token::Operator(Operator::Comma) => input.record(TokenKind::Separator, start),
+        Token::Operator(op) => input.record(TokenKind::Operator(op.clone()), start),
+        Token::Symbol(Symbol::Assign | Symbol::CompoundAssign(_)) => input.record(TokenKind::Assign, start),
+        Token::Symbol(_) => input.record(TokenKind::Paren, start),
+        _ => {}
+    }
+    Ok(token)
+}
+
+pub(crate) fn lex(input: &mut InputReader, allow_idents: bool, options: &SolveOptions) -> Result<Vec<Token>, Error> {
+    lex_with_variables(input, allow_idents, options, &|_| false)
+}
+
+/// the name at the start of the input if it is immediately followed by a '(', as in a function call
+fn peek_call(input: &InputReader) -> Option<String> {
+    let mut name = String::new();
+    while let Some(c) = input.peek_at(name.chars().count()) {
+        if !(c.is_alphanumeric() || c == '_') {
+            break;
+        }
+        name.push(c);
+    }
+    (input.peek_at(name.chars().count()) == Some('(') && keyword(&name).is_none()).then_some(name)
 }
 
-pub(crate) fn lex(input: &mut InputReader, allow_idents: bool) -> Result<Vec<Token>, Error> {
+/// lexes the input like `lex`, where `is_variable` returns true for the names that are defined as variables
+/// rather than functions. With `SolveOptions::implicit_mul_unknown_calls`, a '(' right after one of them
+/// multiplies the variable instead of calling it
+pub(crate) fn lex_with_variables(input: &mut InputReader, allow_idents: bool, options: &SolveOptions, is_variable: &dyn Fn(&str) -> bool) -> Result<Vec<Token>, Error> {
     if input.is_empty() {
-        return Ok(vec![Token::Num(0.0)]);
+        return Ok(vec![Token::Num(Value::Int(0))]);
+    }
+
+    let mut tokens = lex_tokens(input, allow_idents, options, is_variable, 0, None)?;
+    // input with only whitespace is treated the same as empty input
+    if tokens.is_empty() {
+        tokens.push(Token::Num(Value::Int(0)));
     }
 
+    Ok(tokens)
+}
+
+/// lexes tokens until the end of the input, or with a `separator` until the separator or a ')' that isn't inside
+/// of parentheses, which is left to be consumed. `depth` is the number of function calls the tokens are arguments of
+fn lex_tokens(input: &mut InputReader, allow_idents: bool, options: &SolveOptions, is_variable: &dyn Fn(&str) -> bool, depth: usize, separator: Option<char>) -> Result<Vec<Token>, Error> {
     let mut tokens = Vec::new();
+    let mut parens = 0usize;
+    // whether the last token ends an operand, in which case `||` is a logical or rather than two
+    // absolute value bars. A '|' only ends an operand if one was just before it, as it closes a group then
+    let mut after_operand = false;
     while let Some(c) = input.peek() {
+        if parens == 0 && separator.is_some_and(|separator| c == separator || c == ')') {
+            break;
+        }
         match c {
             ' ' | '\n' | '\t' | '\r' => {
                 input.consume();
             }
-            _ => tokens.push(next_token(input, allow_idents)?),
+            '|' if after_operand && input.peek_at(1) == Some('|') => {
+                let start = input.position();
+                input.consume();
+                input.consume();
+                input.record(TokenKind::Operator(Operator::Or), start);
+                tokens.push(Token::Operator(Operator::Or));
+                after_operand = false;
+            }
+            c if allow_idents && options.implicit_mul_unknown_calls && (c.is_alphabetic() || c == '_')
+                && peek_call(input).is_some_and(|name| is_variable(&name)) => {
+                let name = peek_call(input).unwrap();
+                let start = input.position();
+                for _ in name.chars() {
+                    input.consume();
+                }
+                input.record(TokenKind::Identifier, start);
+                tokens.push(Token::Identifier(name));
+                tokens.push(Token::Operator(Operator::Mul));
+                after_operand = false;
+            }
+            _ => {
+                let token = next_token(input, allow_idents, options, depth)?;
+                match token {
+                    Token::Symbol(Symbol::LeftParen) => parens += 1,
+                    Token::Symbol(Symbol::RightParen) => parens = parens.saturating_sub(1),
+                    _ => {}
+                }
+                after_operand = match token {
+                    Token::Symbol(Symbol::RightParen) => true,
+                    Token::Symbol(Symbol::Bar) => after_operand,
+                    Token::Operator(_) | Token::Symbol(_) => false,
+                    _ => true,
+                };
+                tokens.push(token);
+            }
         }
     }
-
     Ok(tokens)
-}
\ No newline at end of file
+}
+
+/// adds the names of all variables and functions used by the tokens to the given sets
+pub(crate) fn collect_identifiers(tokens: &[Token], variables: &mut BTreeSet<String>, functions: &mut BTreeSet<String>) {
+    for token in tokens {
+        match token {
+            Token::Identifier(name) => {
+                variables.insert(name.to_string());
+            }
+            Token::Function(name, args) => {
+                functions.insert(name.to_string());
+                collect_identifiers(args, variables, functions);
+            }
+            Token::Group(stack) => {
+                for operand in stack.operands() {
+                    collect_identifiers(core::slice::from_ref(operand), variables, functions);
+                }
+            }
+            _ => {}
+        }
+    }
+}
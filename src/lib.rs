@@ -1,13 +1,52 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(all(not(feature = "std"), not(feature = "libm")))]
+compile_error!("calc_lib requires either the `std` feature or, for `no_std` builds, the `libm` feature");
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::fmt::{Display, Formatter};
+#[cfg(feature = "std")]
 use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
 use crate::input_reader::InputReader;
-use crate::interpret::{interpret, interpret_with_definitions};
+use crate::interpret::{interpret, interpret_int, interpret_reusing, interpret_value, interpret_with_definitions, interpret_with_warnings, EvalState, Scope, Scratch};
 
 pub(crate) mod lex;
 pub(crate) mod input_reader;
 pub(crate) mod postfix;
 pub(crate) mod interpret;
 pub(crate) mod operator;
+pub(crate) mod options;
+pub(crate) mod ast;
+pub(crate) mod expression;
+pub(crate) mod calculator;
+pub(crate) mod value;
+pub(crate) mod format;
+pub(crate) mod math;
+pub(crate) mod diagnostics;
+
+pub use ast::{Ast, Expr};
+pub use calculator::Calculator;
+pub use diagnostics::{Diagnostics, Stage, Warning};
+pub use expression::{ExprStats, Expression};
+pub use format::{format_radix, format_result, FormatOptions};
+pub use lex::{PublicToken, TokenKind};
+pub use operator::{Associativity, Operator};
+pub use options::{DivByZeroMode, ModuloMode, OverflowMode, SolveOptions, SolveOptionsBuilder};
+pub use postfix::PostfixItem;
+pub use value::Value;
 
 /// An enum representing an error that occurred
 /// This allows for user handling of errors while still allowing them to just be
@@ -62,6 +101,21 @@ pub enum Error {
         /// The name of the undefined function
         name: String
     },
+    /// When the input calls a function that `SolveOptions::allowed_functions` doesn't allow.
+    FunctionNotAllowed {
+        /// The name of the function
+        name: String
+    },
+    /// When a variable is called like a function, i.e. `x(2)` where `x` is a variable.
+    NotCallable {
+        /// The name of the variable
+        name: String
+    },
+    /// When a function is used like a variable, i.e. `f + 1` where `f` is a function.
+    NotAValue {
+        /// The name of the function
+        name: String
+    },
     /// When a function is called with the wrong number of arguments.
     InvalidArgumentCount {
         /// The name of the function
@@ -85,20 +139,52 @@ pub enum Error {
     },
     /// When the interpreter expects an operator (i.e. after a number) but gets something else.
     MissingOperator,
+    /// When two operands are next to each other without an operator between them (i.e. `2 3`).
+    AdjacentOperands {
+        /// the first operand
+        left: String,
+        /// the operand after it
+        right: String,
+    },
+    /// When the digits of a number are grouped with spaces (i.e. `1 000`), which would otherwise be two numbers
+    /// next to each other.
+    SpaceInNumber {
+        /// the number as it was written
+        number: String,
+    },
+    /// When function calls, parentheses or absolute value bars are nested deeper than `SolveOptions::max_depth`
+    DepthExceeded,
+    /// When an evaluation does more operator applications and function calls than `SolveOptions::max_operations` allows
+    OperationLimitExceeded,
+    /// When an integer operation's result is too large or small to be stored, see `evaluate_int`.
+    Overflow,
+    /// When a pair of parentheses has nothing in it (i.e. `3 * ()`). Functions can still be called without arguments.
+    EmptyParentheses,
     /// When there is an incomplete pair of parentheses (i.e. open with no close or vice versa).
     MismatchedParentheses {
-        /// The parenthesis that was found ('(' or ')')
+        /// The parenthesis that was found ('(' or ')', or '[' from `evaluate_vec`)
         found: char,
-        /// The parenthesis that was missing ('(' or ')')
+        /// The parenthesis that was missing ('(' or ')', or ']' from `evaluate_vec`)
         missing: char
     },
     /// Custom error messages.
     /// contains a String of the error message.
-    /// this is not used by this program and is only used for custom error messages by the user
+    /// this is mostly for custom error messages by the user, and is otherwise only used when reading input
+    /// fails (see `evaluate_reader`) and for reserved names (see `Definitions::register_checked`)
     Other(String),
+    /// An error of any type returned by a function, which can be downcast back to its type
+    /// after the evaluation fails. See `Error::custom`.
+    #[cfg(feature = "std")]
+    Custom(CustomError),
 }
 
 impl Error {
+    /// Wraps an error of any type to be returned by a function, see `CustomError`.
+    #[cfg(feature = "std")]
+    pub fn custom<E: std::error::Error + Send + Sync + 'static>(error: E) -> Error {
+        Error::Custom(CustomError(Arc::new(error)))
+    }
+
     pub fn arg_count<S: Into<String>>(name: S, expected: usize, got: usize) -> Error {
         Error::InvalidArgumentCount {
             name: name.into(),
@@ -108,8 +194,80 @@ impl Error {
     }
 }
 
+/// An error of any type returned by a function through `Error::Custom`.
+///
+/// The error is shared rather than copied when the `Error` is cloned, and two `CustomError`s
+/// are only equal when they share the same error.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{evaluate_with_defined, Error, Functions};
+///
+/// #[derive(Debug)]
+/// struct SensorOffline(u32);
+///
+/// impl std::fmt::Display for SensorOffline {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         write!(f, "sensor {} is offline", self.0)
+///     }
+/// }
+///
+/// impl std::error::Error for SensorOffline {}
+///
+/// let mut funcs = Functions::new();
+/// funcs.register("sensor", |args| Err(Error::custom(SensorOffline(args[0] as u32))));
+///
+/// match evaluate_with_defined("sensor(3) * 2", None, Some(&funcs)) {
+///     Err(Error::Custom(e)) => assert_eq!(e.downcast_ref::<SensorOffline>().unwrap().0, 3),
+///     other => panic!("unexpected result: {:?}", other),
+/// }
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct CustomError(Arc<dyn std::error::Error + Send + Sync>);
+
+#[cfg(feature = "std")]
+impl CustomError {
+    /// Returns the error.
+    pub fn get(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self.0.as_ref()
+    }
+
+    /// Returns the error as `T`, or None if it is a different type.
+    pub fn downcast_ref<T: std::error::Error + 'static>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+}
+
+#[cfg(feature = "std")]
+impl PartialEq for CustomError {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Eq for CustomError {}
+
+#[cfg(feature = "std")]
+impl Display for CustomError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Custom(e) => Some(e.get()),
+            _ => None,
+        }
+    }
+}
+
 impl Display for Error {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match self {
             Error::DivByZero => write!(f, "Can't divide by zero"),
             Error::NegativeExponent => write!(f, "Can't raise a value to a negative power"),
@@ -118,21 +276,37 @@ impl Display for Error {
             Error::Expected { expected, found } => write!(f, "Expected '{}', found '{}'", expected, found),
             Error::UnexpectedEOI => write!(f, "Unexpected end of input"),
             Error::InvalidOperand { op } => write!(f, "Invalid operand: {}", op),
+            Error::InvalidOperator { op } if op == "=" => write!(f, "Invalid operator: = (variables can only be assigned with `name = expression` \
+                at the start of a statement, see `evaluate_program`. Did you mean to compare them with `==`?)"),
             Error::InvalidOperator { op } => write!(f, "Invalid operator: {}", op),
             Error::InvalidExpression { reason } => write!(f, "Invalid expression: {}", reason),
             Error::UndefinedVariable { name } => write!(f, "Undefined variable: {}", name),
             Error::UndefinedFunction { name } => write!(f, "Undefined function: {}", name),
+            Error::FunctionNotAllowed { name } => write!(f, "Function not allowed: {}", name),
+            Error::NotCallable { name } => write!(f, "'{}' is a variable, not a function", name),
+            Error::NotAValue { name } => write!(f, "'{}' is a function, not a variable, did you mean to call it ({}(...))?", name, name),
             Error::InvalidArgumentCount { name, expected, got } => write!(f, "Invalid argument count for function '{}': expected {}, got {}", name, expected, got),
             Error::InvalidArgument { name, value } => write!(f, "Invalid argument for function '{}': {}", name, value),
             Error::InvalidLeadingOperator { op } => write!(f, "Invalid leading operator: {}", op),
             Error::MissingOperator => write!(f, "Missing operator"),
+            Error::SpaceInNumber { number } => write!(f, "Numbers can't contain spaces, did you mean {}?", number.replace(' ', "")),
+            Error::AdjacentOperands { left, right } => write!(f, "Missing operator between {} and {}, did you mean to multiply them ({} * {})?", left, right, left, right),
+            Error::Overflow => write!(f, "Integer overflow"),
+            Error::DepthExceeded => write!(f, "Maximum nesting depth exceeded"),
+            Error::OperationLimitExceeded => write!(f, "Maximum number of operations exceeded"),
+            Error::EmptyParentheses => write!(f, "Empty parentheses"),
             Error::MismatchedParentheses { found, missing } => write!(f, "Mismatched parentheses: found '{}', missing '{}'", found, missing),
             Error::Other(s) => write!(f, "{}", s),
+            #[cfg(feature = "std")]
+            Error::Custom(e) => write!(f, "{}", e),
         }
     }
 }
 
 /// A list of definitions to pass into the crate to be used in the interpreter.
+///
+/// `mod` and `div` are operators when identifiers are allowed (`17 mod 5`), so they can't be used as the names of variables.
+#[derive(Debug, Clone)]
 pub struct Definitions {
     pub(crate) map: HashMap<String, f64>,
 }
@@ -145,11 +319,84 @@ impl Definitions {
         }
     }
 
+    /// Create a definition map with common physical constants, as their magnitudes in SI units (CODATA 2018):
+    ///
+    /// | name   | constant                      | value            | unit          |
+    /// |--------|-------------------------------|------------------|---------------|
+    /// | `c`    | speed of light                | 299792458        | m/s           |
+    /// | `g`    | standard gravity              | 9.80665          | m/s²          |
+    /// | `G`    | gravitational constant        | 6.67430e-11      | m³/(kg·s²)    |
+    /// | `h`    | Planck constant               | 6.62607015e-34   | J·s           |
+    /// | `hbar` | reduced Planck constant       | 1.054571817e-34  | J·s           |
+    /// | `Na`   | Avogadro constant             | 6.02214076e23    | 1/mol         |
+    /// | `kB`   | Boltzmann constant            | 1.380649e-23     | J/K           |
+    /// | `R`    | gas constant                  | 8.314462618      | J/(mol·K)     |
+    /// | `qe`   | elementary charge             | 1.602176634e-19  | C             |
+    /// | `me`   | electron mass                 | 9.1093837015e-31 | kg            |
+    /// | `eps0` | vacuum permittivity           | 8.8541878128e-12 | F/m           |
+    /// | `mu0`  | vacuum permeability           | 1.25663706212e-6 | N/A²          |
+    ///
+    /// The names are case sensitive (`g` and `G` are different constants). Registering a variable
+    /// with the same name replaces the constant, so a variable named `c` can't be used along with it.
+    ///
+    /// # Usage Example:
+    /// ```
+    /// use calc_lib::{Definitions, evaluate_with_defined};
+    ///
+    /// let defs = Definitions::with_physics();
+    /// assert_eq!(evaluate_with_defined("2 * c", Some(&defs), None), Ok(599584916.0));
+    /// ```
+    pub fn with_physics() -> Self {
+        let mut definitions = Definitions::new();
+        definitions.register("c", 299_792_458);
+        definitions.register("g", 9.80665);
+        definitions.register("G", 6.67430e-11);
+        definitions.register("h", 6.62607015e-34);
+        definitions.register("hbar", 1.054571817e-34);
+        definitions.register("Na", 6.02214076e23);
+        definitions.register("kB", 1.380649e-23);
+        definitions.register("R", 8.314462618);
+        definitions.register("qe", 1.602176634e-19);
+        definitions.register("me", 9.1093837015e-31);
+        definitions.register("eps0", 8.8541878128e-12);
+        definitions.register("mu0", 1.25663706212e-6);
+        definitions
+    }
+
     /// register a new definition to the map
     pub fn register<S: Into<String>, N: Into<f64>>(&mut self, name: S, value: N) {
         self.map.insert(name.into(), value.into());
     }
 
+    /// The names `register_checked` rejects: the operator keywords `mod` and `div`, the `inf` and `nan` literals
+    /// (see `SolveOptions::allow_special_floats`), and the names of the functions in `Functions::default`
+    /// and `Functions::with_rng`.
+    pub const RESERVED: &'static [&'static str] = &[
+        "mod", "div", "inf", "nan",
+        "log", "sqrt", "cbrt", "root", "sin", "cos", "tan", "asin", "acos", "atan", "atan2", "sinh", "cosh", "tanh",
+        "sum", "avg", "min", "max", "if", "clamp", "sign", "gcd", "lcm", "percent_of", "pct_change", "rand", "randint",
+    ];
+
+    /// register a new definition to the map like `register`, unless the name is one of `Definitions::RESERVED`,
+    /// which would be confusing as a variable. A reserved name returns `Error::Other` and isn't registered.
+    ///
+    /// # Usage Example:
+    /// ```
+    /// use calc_lib::Definitions;
+    ///
+    /// let mut defs = Definitions::new();
+    /// assert!(defs.register_checked("x", 1.0).is_ok());
+    /// assert!(defs.register_checked("sin", 1.0).is_err());
+    /// ```
+    pub fn register_checked<S: Into<String>, N: Into<f64>>(&mut self, name: S, value: N) -> Result<(), Error> {
+        let name = name.into();
+        if Definitions::RESERVED.contains(&name.as_str()) {
+            return Err(Error::Other(format!("'{}' is a reserved name and can't be used as a variable", name)));
+        }
+        self.register(name, value);
+        Ok(())
+    }
+
     pub fn exists<S: Into<String>>(&self, ident: S) -> bool {
         self.map.contains_key(ident.into().as_str())
     }
@@ -158,11 +405,58 @@ impl Definitions {
     pub(crate) fn get<S: Into<String>>(&self, ident: S) -> Option<&f64> {
         self.map.get(ident.into().as_str())
     }
+
+    /// remove a definition from the map, returning its value if it was defined
+    pub fn remove(&mut self, name: &str) -> Option<f64> {
+        self.map.remove(name)
+    }
+
+    /// remove every definition from the map
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// add every definition from `other`, replacing any definitions with the same names
+    pub fn extend(&mut self, other: Definitions) {
+        self.map.extend(other.map);
+    }
+
+    /// iterate over the names and values of every definition, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &f64)> {
+        self.map.iter()
+    }
+}
+
+impl Default for Definitions {
+    /// create a new definition map with no definitions, the same as `Definitions::new()`
+    fn default() -> Self {
+        Definitions::new()
+    }
+}
+
+/// A function stored in `Functions`
+pub(crate) enum Function<'a> {
+    /// A function implemented by a closure
+    Native {
+        f: Box<dyn Fn(Vec<f64>) -> Result<f64, Error> + 'a>,
+        /// the number of arguments it takes, if it was registered with one
+        arity: Option<usize>,
+        /// its parameters for help text, see `Functions::set_signature`
+        signature: Option<String>,
+    },
+    /// A function defined by an expression, see `Calculator::define_function`
+    Defined {
+        params: Vec<String>,
+        body: Expression,
+    },
 }
 
 /// A list of definitions of functions to pass into the interpreter to solve for the variables.
+///
+/// The operator keywords `mod` and `div` always take precedence over functions with the same name,
+/// so `10 mod(3)` is `10 % 3` even if a function named `mod` is registered, and such a function can't be called.
 pub struct Functions<'a> {
-    pub(crate) functions: HashMap<String, Box<dyn Fn(Vec<f64>) -> Result<f64, Error> + 'a>>,
+    pub(crate) functions: HashMap<String, Function<'a>>,
 }
 
 impl<'a> Functions<'a> {
@@ -173,9 +467,36 @@ impl<'a> Functions<'a> {
         }
     }
 
-    /// register a function
-    pub fn register<S: Into<String>, F: Fn(Vec<f64>) -> Result<f64, Error> + 'a + Copy>(&mut self, name: S, f: F) {
-        self.functions.insert(name.into(), Box::new(f));
+    /// register a function. The function can keep state between calls (i.e. in a `Cell`),
+    /// as long as `SolveOptions::memoize` is off.
+    pub fn register<S: Into<String>, F: Fn(Vec<f64>) -> Result<f64, Error> + 'a>(&mut self, name: S, f: F) {
+        self.functions.insert(name.into(), Function::Native { f: Box::new(f), arity: None, signature: None });
+    }
+
+    /// register a function that takes exactly `arity` arguments. If a different number is given,
+    /// `Error::InvalidArgumentCount` is returned without calling `f`, and `Expression::validate`
+    /// can report the mistake before the expression is evaluated.
+    pub fn register_with_arity<S: Into<String>, F: Fn(Vec<f64>) -> Result<f64, Error> + 'a>(&mut self, name: S, arity: usize, f: F) {
+        self.functions.insert(name.into(), Function::Native { f: Box::new(f), arity: Some(arity), signature: None });
+    }
+
+    /// register a function that accepts any number of arguments, as long as at least `min_args`
+    /// are given. If fewer are given, `Error::InvalidArgumentCount` is returned without calling `f`.
+    pub fn register_variadic<S: Into<String>, F: Fn(Vec<f64>) -> Result<f64, Error> + 'a>(&mut self, name: S, min_args: usize, f: F) {
+        let name = name.into();
+        let fn_name = name.clone();
+        let f = Box::new(move |args: Vec<f64>| {
+            if args.len() < min_args {
+                return Err(Error::arg_count(fn_name.as_str(), min_args, args.len()));
+            }
+            f(args)
+        });
+        self.functions.insert(name, Function::Native { f, arity: None, signature: None });
+    }
+
+    /// register a function defined by an expression, which is evaluated with `params` bound to the arguments
+    pub(crate) fn register_defined<S: Into<String>>(&mut self, name: S, params: Vec<String>, body: Expression) {
+        self.functions.insert(name.into(), Function::Defined { params, body });
     }
 
     /// check if a function exists
@@ -183,61 +504,349 @@ impl<'a> Functions<'a> {
         self.functions.contains_key(ident.into().as_str())
     }
 
-    pub(crate) fn get<S: Into<String>>(&self, ident: S) -> Option<&Box<dyn Fn(Vec<f64>) -> Result<f64, Error> + 'a>> {
+    /// remove a function, returning true if it existed
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.functions.remove(name).is_some()
+    }
+
+    /// remove every function, including the default functions
+    pub fn clear(&mut self) {
+        self.functions.clear();
+    }
+
+    /// add every function from `other`, replacing any functions with the same names
+    pub fn extend(&mut self, other: Functions<'a>) {
+        self.functions.extend(other.functions);
+    }
+
+    /// set the parameters of a function to show in help text, see `signatures`.
+    /// Returns false if the function doesn't exist or was defined by an expression, which already has its parameters.
+    pub fn set_signature<S: Into<String>>(&mut self, name: &str, params: S) -> bool {
+        match self.functions.get_mut(name) {
+            Some(Function::Native { signature, .. }) => {
+                *signature = Some(params.into());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// list the name and parameters of every function, sorted by name, for help text (i.e. `log` and `base, value`).
+    /// Functions without a signature are listed with `...` as their parameters.
+    ///
+    /// ```
+    /// use calc_lib::Functions;
+    ///
+    /// let help: Vec<String> = Functions::default().signatures().iter()
+    ///     .map(|(name, params)| format!("{}({})", name, params))
+    ///     .collect();
+    /// assert!(help.contains(&"log(base, value)".to_string()));
+    /// ```
+    pub fn signatures(&self) -> Vec<(String, String)> {
+        let mut signatures: Vec<(String, String)> = self.functions.iter().map(|(name, function)| {
+            let params = match function {
+                Function::Native { signature: Some(signature), .. } => signature.to_string(),
+                Function::Native { .. } => "...".to_string(),
+                Function::Defined { params, .. } => params.join(", "),
+            };
+            (name.to_string(), params)
+        }).collect();
+        signatures.sort();
+        signatures
+    }
+
+    /// iterate over the names of every function, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.functions.keys()
+    }
+
+    /// the number of arguments a function takes, if it only takes a certain number
+    pub(crate) fn arity(&self, ident: &str) -> Option<usize> {
+        match self.get(ident)? {
+            Function::Native { arity, .. } => *arity,
+            Function::Defined { params, .. } => Some(params.len()),
+        }
+    }
+
+    pub(crate) fn get<S: Into<String>>(&self, ident: S) -> Option<&Function<'a>> {
         let ident = ident.into();
         if !self.functions.contains_key(&ident) {
             return None;
         }
         self.functions.get(&ident)
     }
+
+    /// calls a function with already evaluated arguments.
+    /// `definitions` are the definitions of the caller, which are visible to defined functions,
+    /// and `depth` is the number of function calls the call is nested in.
+    /// calls the function, where `state` is the state of the evaluation it was called from,
+    /// so the body of a defined function counts towards the same `SolveOptions::max_operations`
+    pub(crate) fn call(&self, ident: &str, args: Vec<f64>, definitions: Option<&Definitions>, depth: usize, state: &mut EvalState) -> Result<f64, Error> {
+        match self.get(ident) {
+            None => Err(Error::UndefinedFunction { name: ident.to_string() }),
+            Some(Function::Native { arity: Some(arity), .. }) if args.len() != *arity => Err(Error::arg_count(ident, *arity, args.len())),
+            Some(Function::Native { f, .. }) => f(args),
+            Some(Function::Defined { params, body }) => {
+                if args.len() != params.len() {
+                    return Err(Error::arg_count(ident, params.len(), args.len()));
+                }
+                let mut scope = definitions.cloned().unwrap_or_else(Definitions::new);
+                for (param, arg) in params.iter().zip(args) {
+                    scope.register(param.as_str(), arg);
+                }
+                body.eval_at_depth(Some(&scope), Some(self), depth + 1, state)
+            }
+        }
+    }
+}
+
+impl Functions<'_> {
+    /// create a new list of functions with the default functions and two random number functions,
+    /// which return the same sequence of numbers for the same `seed`:
+    /// - `rand()` is a random number from 0 up to (but not including) 1, and `rand(min, max)` from `min` up to `max`
+    /// - `randint(min, max)` is a random integer from `min` to `max`, including both
+    ///
+    /// As the functions return a different number every time they are called, `SolveOptions::memoize` must be off.
+    ///
+    /// # Usage Example:
+    /// ```
+    /// use calc_lib::{evaluate_with_defined, Functions};
+    ///
+    /// let (a, b) = (Functions::with_rng(42), Functions::with_rng(42));
+    /// let roll = evaluate_with_defined("randint(1, 6)", None, Some(&a)).unwrap();
+    /// assert_eq!(evaluate_with_defined("randint(1, 6)", None, Some(&b)), Ok(roll));
+    /// ```
+    pub fn with_rng(seed: u64) -> Self {
+        let mut funcs = Functions::default();
+        let state = Rc::new(Cell::new(seed));
+
+        let rand_state = state.clone();
+        funcs.register("rand", move |args| {
+            let (min, max) = match args.len() {
+                0 => (0.0, 1.0),
+                2 => (args[0], args[1]),
+                got => return Err(Error::arg_count("rand", 2, got)),
+            };
+            if min.partial_cmp(&max).is_none_or(|o| o.is_gt()) {
+                return Err(Error::InvalidArgument { name: "rand".to_string(), value: format!("min {} is greater than max {}", min, max) });
+            }
+            // the top 53 bits fill the mantissa of a float from 0 to 1
+            let unit = (next_random(&rand_state) >> 11) as f64 / (1u64 << 53) as f64;
+            Ok(min + unit * (max - min))
+        });
+
+        funcs.register_with_arity("randint", 2, move |args| {
+            let min = integer_arg("randint", args[0])?;
+            let max = integer_arg("randint", args[1])?;
+            if min > max {
+                return Err(Error::InvalidArgument { name: "randint".to_string(), value: format!("min {} is greater than max {}", min, max) });
+            }
+            let range = (max as i128 - min as i128 + 1) as u128;
+            Ok((min as i128 + (next_random(&state) as u128 % range) as i128) as f64)
+        });
+
+        funcs.set_signature("rand", "[min, max]");
+        funcs.set_signature("randint", "min, max");
+
+        funcs
+    }
+}
+
+/// advances the state of a SplitMix64 generator, returning the next random number
+fn next_random(state: &Cell<u64>) -> u64 {
+    let next = state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+    state.set(next);
+    let mut z = next;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
 }
 
 impl Default for Functions<'_> {
     /// create a new list of functions with the default functions:
-    /// `log`, `sqrt`, `sin`, `cos`, `tan`
+    /// `log`, `sqrt`, `cbrt`, `root`, `sin`, `cos`, `tan`, `asin`, `acos`, `atan`, `atan2`, `sinh`, `cosh`, `tanh`, `sum`, `avg`, `min`, `max`, `if`, `clamp`, `sign`, `gcd`, `lcm`, `percent_of`, `pct_change`
+    ///
+    /// Angles are in radians, and `atan2(y, x)` is the angle of the point `(x, y)`.
+    ///
+    /// `percent_of(part, whole)` is the percentage `part` is of `whole`, and `pct_change(old, new)` is
+    /// the percentage `new` is greater than `old` by. Both return `Error::DivByZero` for a base of 0.
+    ///
+    /// Each argument can be any expression, i.e. `max(x + 1, 2)`.
+    ///
+    /// `if(condition, a, b)` returns `a` when `condition` is not zero and `b` otherwise, i.e. `if(x > 1, x * 2, 0)`.
+    /// Arguments are evaluated before the function is called, so a function call passed as
+    /// `a` or `b` is always made, and its error is returned even if the other one is chosen.
     fn default() -> Self {
         let mut funcs = Functions::new();
-        funcs.register("log", |args| {
-            if args.len() != 2 {
-                return Err(Error::arg_count("log", 2, args.len()));
+        funcs.register_with_arity("log", 2, |args| {
+            Ok(math::log(args[1], args[0]))
+        });
+
+        funcs.register_with_arity("sqrt", 1, |args| {
+            Ok(math::sqrt(args[0]))
+        });
+
+        funcs.register_with_arity("cbrt", 1, |args| {
+            Ok(math::cbrt(args[0]))
+        });
+
+        funcs.register_with_arity("root", 2, |args| {
+            let (n, x) = (args[0], args[1]);
+            if n == 0.0 {
+                return Err(Error::InvalidArgument { name: "root".to_string(), value: "the 0th root is undefined".to_string() });
             }
-            Ok(args[1].log(args[0]))
+            // only odd roots of negative numbers are real numbers
+            let odd = math::trunc(n) == n && n % 2.0 != 0.0;
+            if x < 0.0 && !odd {
+                return Err(Error::InvalidArgument { name: "root".to_string(), value: format!("the {}th root of the negative number {} is not a real number", n, x) });
+            }
+            let root = math::powf(x.abs(), 1.0 / n);
+            // 1 / n usually isn't exact, so an exact root can come out slightly off (i.e. root(3, 27) = 3.0000000000000004)
+            let rounded = math::round(root);
+            let root = if math::powf(rounded, n) == x.abs() { rounded } else { root };
+            Ok(if x < 0.0 { -root } else { root })
+        });
+
+        funcs.register_with_arity("sin", 1, |args| {
+            Ok(math::sin(args[0]))
+        });
+
+        funcs.register_with_arity("cos", 1, |args| {
+            Ok(math::cos(args[0]))
+        });
+
+        funcs.register_with_arity("tan", 1, |args| {
+            Ok(math::tan(args[0]))
+        });
+
+        funcs.register_with_arity("asin", 1, |args| {
+            Ok(math::asin(args[0]))
+        });
+
+        funcs.register_with_arity("acos", 1, |args| {
+            Ok(math::acos(args[0]))
+        });
+
+        funcs.register_with_arity("atan", 1, |args| {
+            Ok(math::atan(args[0]))
+        });
+
+        funcs.register_with_arity("atan2", 2, |args| {
+            Ok(math::atan2(args[0], args[1]))
+        });
+
+        funcs.register_with_arity("sinh", 1, |args| {
+            Ok(math::sinh(args[0]))
+        });
+
+        funcs.register_with_arity("cosh", 1, |args| {
+            Ok(math::cosh(args[0]))
+        });
+
+        funcs.register_with_arity("tanh", 1, |args| {
+            Ok(math::tanh(args[0]))
+        });
+
+        funcs.register_variadic("sum", 1, |args| Ok(args.iter().sum()));
+
+        funcs.register_variadic("avg", 1, |args| Ok(args.iter().sum::<f64>() / args.len() as f64));
+
+        funcs.register_variadic("min", 1, |args| Ok(args.iter().copied().fold(f64::INFINITY, f64::min)));
+
+        funcs.register_variadic("max", 1, |args| Ok(args.iter().copied().fold(f64::NEG_INFINITY, f64::max)));
+
+        funcs.register_with_arity("if", 3, |args| {
+            Ok(if args[0] != 0.0 { args[1] } else { args[2] })
         });
 
-        funcs.register("sqrt", |args| {
-            if args.len() != 1 {
-                return Err(Error::arg_count("sqrt", 1, args.len()));
+        funcs.register_with_arity("clamp", 3, |args| {
+            // also rejects NaN bounds, which f64::clamp would panic on
+            if args[1].partial_cmp(&args[2]).is_none_or(|o| o.is_gt()) {
+                return Err(Error::InvalidArgument { name: "clamp".to_string(), value: format!("min {} is greater than max {}", args[1], args[2]) });
             }
-            Ok(args[0].sqrt())
+            Ok(args[0].clamp(args[1], args[2]))
+        });
+
+        funcs.register_with_arity("sign", 1, |args| {
+            Ok(if args[0] > 0.0 {
+                1.0
+            } else if args[0] < 0.0 {
+                -1.0
+            } else {
+                // 0 (and NaN) have no sign
+                args[0] * 0.0
+            })
+        });
+
+        funcs.register_with_arity("gcd", 2, |args| {
+            let a = integer_arg("gcd", args[0])?;
+            let b = integer_arg("gcd", args[1])?;
+            Ok(gcd(a.unsigned_abs(), b.unsigned_abs()) as f64)
         });
 
-        funcs.register("sin", |args| {
-            if args.len() != 1 {
-                return Err(Error::arg_count("sin", 1, args.len()));
+        funcs.register_with_arity("lcm", 2, |args| {
+            let a = integer_arg("lcm", args[0])?.unsigned_abs();
+            let b = integer_arg("lcm", args[1])?.unsigned_abs();
+            if a == 0 || b == 0 {
+                return Ok(0.0);
             }
-            Ok(args[0].sin())
+            // a u128 can hold the product of any two u64s
+            Ok(((a / gcd(a, b)) as u128 * b as u128) as f64)
         });
 
-        funcs.register("cos", |args| {
-            if args.len() != 1 {
-                return Err(Error::arg_count("cos", 1, args.len()));
+        funcs.register_with_arity("percent_of", 2, |args| {
+            if args[1] == 0.0 {
+                return Err(Error::DivByZero);
             }
-            Ok(args[0].cos())
+            Ok(args[0] / args[1] * 100.0)
         });
 
-        funcs.register("tan", |args| {
-            if args.len() != 1 {
-                return Err(Error::arg_count("tan", 1, args.len()));
+        funcs.register_with_arity("pct_change", 2, |args| {
+            if args[0] == 0.0 {
+                return Err(Error::DivByZero);
             }
-            Ok(args[0].tan())
+            // relative to the size of the old value, so a rise is positive even from a negative value
+            Ok((args[1] - args[0]) / args[0].abs() * 100.0)
         });
 
+        let signatures = [
+            ("log", "base, value"), ("sqrt", "value"), ("cbrt", "value"), ("root", "n, value"),
+            ("sin", "value"), ("cos", "value"), ("tan", "value"), ("asin", "value"), ("acos", "value"), ("atan", "value"),
+            ("atan2", "y, x"), ("sinh", "value"), ("cosh", "value"), ("tanh", "value"),
+            ("sum", "values..."), ("avg", "values..."), ("min", "values..."), ("max", "values..."),
+            ("if", "condition, a, b"), ("clamp", "value, min, max"), ("sign", "value"), ("gcd", "a, b"), ("lcm", "a, b"),
+            ("percent_of", "part, whole"), ("pct_change", "old, new"),
+        ];
+        for (name, params) in signatures {
+            funcs.set_signature(name, params);
+        }
+
         funcs
     }
 }
 
+/// converts a function argument to an integer, returning `Error::InvalidArgument`
+/// if it has a fractional part or doesn't fit in an `i64`
+fn integer_arg(name: &str, value: f64) -> Result<i64, Error> {
+    if math::trunc(value) != value || value.abs() >= i64::MAX as f64 {
+        return Err(Error::InvalidArgument { name: name.to_string(), value: format!("{} is not an integer", value) });
+    }
+    Ok(value as i64)
+}
+
+/// the greatest common divisor of `a` and `b` using the Euclidean algorithm
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
 /// Evaluates an equation in infix notation using the shunting yard algorithm.
 /// This function does not accept defined variables or functions. See `evaluate_with_defined`.
+///
+/// Operations on integer literals (including `^` with a non-negative integer exponent) are exact,
+/// and are only calculated as floats once a result is too large for an `i128` (i.e. `2 ^ 200`).
 /// # Usage Example:
 /// ```
 /// use calc_lib::evaluate;
@@ -249,10 +858,47 @@ impl Default for Functions<'_> {
 /// assert_eq!(eval.unwrap() as i64, 9);
 /// ```
 pub fn evaluate<S: Into<String>>(input: S) -> Result<f64, Error> {
+    evaluate_with_options(input, None, None, &SolveOptions::default())
+}
+
+/// Evaluates an expression like `evaluate`, but keeps the result an integer when the numbers
+/// and operations were all integral, so it can be printed as `9` instead of `9.0`.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{evaluate_value, Value};
+///
+/// assert_eq!(evaluate_value("(1 + 2) * 3"), Ok(Value::Int(9)));
+/// assert_eq!(evaluate_value("7 / 2"), Ok(Value::Float(3.5)));
+/// assert_eq!(evaluate_value("2.0 * 2").unwrap().to_string(), "4.0");
+/// ```
+pub fn evaluate_value<S: Into<String>>(input: S) -> Result<Value, Error> {
+    let options = SolveOptions::default();
+    let mut input = InputReader::new(input.into());
+    let mut tokens = lex::lex(&mut input, false, &options)?;
+    let mut shunted = postfix::shunting_yard(&mut tokens, &options)?;
+    interpret_value(&mut shunted, &options)
+}
+
+/// Evaluates an expression like `evaluate`, along with warnings about problems that aren't errors, such as
+/// an integer that is too large to be exact as an `f64`. Values calculated on the way to the result are
+/// checked as well, so `1 / 2 ^ 2000` warns that `2 ^ 2000` is infinite even though the result is 0.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{evaluate_with_warnings, Warning};
+///
+/// assert_eq!(evaluate_with_warnings("2 + 2"), Ok((4.0, vec![])));
+/// let (result, warnings) = evaluate_with_warnings("9007199254740992 + 1").unwrap();
+/// assert_eq!(result, 9007199254740992.0);
+/// assert_eq!(warnings, vec![Warning::PrecisionLoss]);
+/// ```
+pub fn evaluate_with_warnings<S: Into<String>>(input: S) -> Result<(f64, Vec<Warning>), Error> {
+    let options = SolveOptions::default();
     let mut input = InputReader::new(input.into());
-    let mut tokens = lex::lex(&mut input, false)?;
-    let mut shunted = postfix::shunting_yard(&mut tokens)?;
-    interpret(&mut shunted)
+    let mut tokens = lex::lex(&mut input, false, &options)?;
+    let mut shunted = postfix::shunting_yard(&mut tokens, &options)?;
+    interpret_with_warnings(&mut shunted, &options).map(|(result, warnings)| (result.as_f64(), warnings))
 }
 
 /// Evaluates an expression in infix notation using the shunting yard algorithm.
@@ -294,29 +940,2386 @@ pub fn evaluate<S: Into<String>>(input: S) -> Result<f64, Error> {
 /// assert_eq!(eval.unwrap() as i64, 4);
 /// ```
 pub fn evaluate_with_defined<S: Into<String>>(input: S, definitions: Option<&Definitions>, functions: Option<&Functions>) -> Result<f64, Error> {
-    let mut input = InputReader::new(input.into());
-    let mut tokens = lex::lex(&mut input, definitions.is_some() || functions.is_some())?;
-    let mut shunted = postfix::shunting_yard(&mut tokens)?;
-    interpret_with_definitions(&mut shunted, definitions, functions)
+    evaluate_with_options(input, definitions, functions, &SolveOptions::default())
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    #[test]
-    fn test1() {
-        let expression = "(2 + 1) - 50 * 12 / 18 - (3 + 1) * 5";
+/// Evaluates an expression like `evaluate_with_defined`, with the variables given as pairs of their names and values
+/// instead of `Definitions`. If a name is given more than once, the last value is used.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::evaluate_with_vars;
+///
+/// assert_eq!(evaluate_with_vars("a + b", &[("a", 2.0), ("b", 3.0)], None), Ok(5.0));
+/// ```
+pub fn evaluate_with_vars<S: Into<String>>(input: S, vars: &[(&str, f64)], functions: Option<&Functions>) -> Result<f64, Error> {
+    let mut definitions = Definitions::new();
+    for (name, value) in vars {
+        definitions.register(*name, *value);
+    }
+    evaluate_with_defined(input, Some(&definitions), functions)
+}
 
-        // (2 + 1) - 50 * 12 / 18 - (3 + 1) * 5
-        // 3 - 33.333333 - 4 * 5
-        // 3 - 33.333333 - 20
-        // 3 - 53.333333
-        // 50.333333
+/// Evaluates an expression like `evaluate_with_defined` as a condition, for using comparisons as predicates.
+/// A result of zero is `false` and any other result is `true`.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{Definitions, evaluate_bool};
+///
+/// let mut defs = Definitions::new();
+/// defs.register("value", 12);
+/// assert_eq!(evaluate_bool("value > 10", Some(&defs), None), Ok(true));
+/// assert_eq!(evaluate_bool("value % 2", Some(&defs), None), Ok(false));
+/// ```
+pub fn evaluate_bool<S: Into<String>>(input: S, definitions: Option<&Definitions>, functions: Option<&Functions>) -> Result<bool, Error> {
+    evaluate_with_defined(input, definitions, functions).map(|result| result != 0.0)
+}
 
-        let eval = evaluate(expression);
-        if eval.is_err() {
-            panic!("Encountered an error evaluating: {}", eval.unwrap_err());
+/// Evaluates an expression read from a reader like `evaluate`, i.e. from a file or standard input.
+/// If the input can't be read, or isn't valid UTF-8, `Error::Other` is returned with the reason.
+/// Only available with the `std` feature.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::evaluate_reader;
+///
+/// let input = std::io::Cursor::new(b"(1 + 2) * 3");
+/// assert_eq!(evaluate_reader(input), Ok(9.0));
+/// ```
+#[cfg(feature = "std")]
+pub fn evaluate_reader<R: std::io::Read>(reader: R) -> Result<f64, Error> {
+    let options = SolveOptions::default();
+    let mut input = InputReader::from_reader(reader).map_err(|e| Error::Other(e.to_string()))?;
+    let mut tokens = lex::lex(&mut input, false, &options)?;
+    let mut shunted = postfix::shunting_yard(&mut tokens, &options)?;
+    interpret(&mut shunted, &options)
+}
+
+/// Evaluates each line of the input as a separate expression like `evaluate`, skipping blank lines.
+/// An error on one line doesn't stop the lines after it from being evaluated.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{evaluate_lines, Error};
+///
+/// let results = evaluate_lines("1 + 2\n\n4 / 0\n3 * 3");
+/// assert_eq!(results, vec![Ok(3.0), Err(Error::DivByZero), Ok(9.0)]);
+/// ```
+pub fn evaluate_lines<S: Into<String>>(input: S) -> Vec<Result<f64, Error>> {
+    input.into()
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(evaluate)
+        .collect()
+}
+
+/// Evaluates a list of expressions in brackets, separated by commas, with the default functions (see `Functions::default`).
+/// Lists can't be nested, and an empty list (`[]`) results in an empty `Vec`.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::evaluate_vec;
+///
+/// assert_eq!(evaluate_vec("[1 + 1, 2 * 3, sqrt(16)]"), Ok(vec![2.0, 6.0, 4.0]));
+/// ```
+pub fn evaluate_vec<S: Into<String>>(input: S) -> Result<Vec<f64>, Error> {
+    let input = input.into();
+    let input = input.trim();
+    let inner = match input.strip_prefix('[') {
+        Some(rest) => rest.strip_suffix(']').ok_or(Error::MismatchedParentheses { found: '[', missing: ']' })?,
+        None => match input.chars().next() {
+            Some(c) => return Err(Error::Expected { expected: "[".to_string(), found: c.to_string() }),
+            None => return Err(Error::UnexpectedEOI),
+        },
+    };
+    if inner.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let functions = Functions::default();
+    let mut results = Vec::new();
+    for item in split_top_level(inner, ',') {
+        if item.trim().is_empty() {
+            return Err(Error::Expected { expected: "expression".to_string(), found: ",".to_string() });
         }
-        println!("{}", eval.unwrap());
+        results.push(evaluate_with_defined(item, None, Some(&functions))?);
+    }
+    Ok(results)
+}
+
+/// Evaluates an expression like `evaluate`, returning either the result or the error message as a string.
+/// This is simpler to use than a `Result` from other languages, i.e. JavaScript through WebAssembly.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::evaluate_to_string;
+///
+/// assert_eq!(evaluate_to_string("2 + 2"), "4");
+/// assert_eq!(evaluate_to_string("1 / 0"), "Can't divide by zero");
+/// ```
+pub fn evaluate_to_string(input: &str) -> String {
+    match evaluate(input) {
+        Ok(result) => result.to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// Evaluates an expression like `evaluate_with_defined`, using the given `SolveOptions`
+/// to change how the expression is evaluated.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{evaluate_with_options, ModuloMode, SolveOptions};
+///
+/// let options = SolveOptions { modulo_mode: ModuloMode::Euclidean, ..Default::default() };
+/// let eval = evaluate_with_options("(0 - 7) % 3", None, None, &options);
+/// assert_eq!(eval.unwrap(), 2.0);
+/// ```
+pub fn evaluate_with_options<S: Into<String>>(input: S, definitions: Option<&Definitions>, functions: Option<&Functions>, options: &SolveOptions) -> Result<f64, Error> {
+    let mut input = InputReader::new(input.into());
+    let allow_idents = definitions.is_some() || functions.is_some() || options.strict_identifiers;
+    let is_variable = |name: &str| definitions.is_some_and(|d| d.exists(name)) && !functions.is_some_and(|f| f.exists(name));
+    let mut tokens = lex::lex_with_variables(&mut input, allow_idents, options, &is_variable)?;
+    let mut shunted = postfix::shunting_yard(&mut tokens, options)?;
+    if definitions.is_none() && functions.is_none() {
+        return interpret(&mut shunted, options);
     }
-}
\ No newline at end of file
+    interpret_with_definitions(&shunted, definitions, functions, options, 0)
+}
+
+/// Evaluates a program of statements separated by `;`, returning the value of the last one.
+/// A statement of the form `name = expression` assigns the value of the expression to `name`
+/// in the definitions, so it can be used by the statements after it. Empty statements are skipped,
+/// and a program without any statements evaluates to 0.
+///
+/// Compound assignments (`+=`, `-=`, `*=`, `/=`, `//=`, `%=` and `^=`) update a variable that is already defined,
+/// so `x += 1` is the same as `x = x + (1)`.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{evaluate_program, Definitions, Functions};
+///
+/// let mut defs = Definitions::new();
+/// let funcs = Functions::default();
+/// assert_eq!(evaluate_program("x = 2; y = 3; x * y", &mut defs, &funcs), Ok(6.0));
+/// // assignments are kept in the definitions
+/// assert_eq!(evaluate_program("x + y", &mut defs, &funcs), Ok(5.0));
+/// ```
+pub fn evaluate_program<S: Into<String>>(input: S, definitions: &mut Definitions, functions: &Functions) -> Result<f64, Error> {
+    evaluate_program_with_options(input, definitions, functions, &SolveOptions::default())
+}
+
+/// Evaluates a program like `evaluate_program`, also returning each assignment made by the program in order.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{evaluate_program_capturing, Definitions, Functions};
+///
+/// let mut defs = Definitions::new();
+/// let (result, assignments) = evaluate_program_capturing("a = 2; b = a + 3; b", &mut defs, &Functions::default()).unwrap();
+/// assert_eq!(result, 5.0);
+/// assert_eq!(assignments, vec![("a".to_string(), 2.0), ("b".to_string(), 5.0)]);
+/// ```
+pub fn evaluate_program_capturing<S: Into<String>>(input: S, definitions: &mut Definitions, functions: &Functions) -> Result<(f64, Vec<(String, f64)>), Error> {
+    let mut assignments = Vec::new();
+    let result = run_program(input.into(), definitions, functions, &SolveOptions::default(), &mut assignments)?;
+    Ok((result, assignments))
+}
+
+/// evaluates a program like `evaluate_program` with the given options
+pub(crate) fn evaluate_program_with_options<S: Into<String>>(input: S, definitions: &mut Definitions, functions: &Functions, options: &SolveOptions) -> Result<f64, Error> {
+    run_program(input.into(), definitions, functions, options, &mut Vec::new())
+}
+
+/// evaluates each statement of a program, adding each assignment made to `assignments`
+fn run_program(input: String, definitions: &mut Definitions, functions: &Functions, options: &SolveOptions, assignments: &mut Vec<(String, f64)>) -> Result<f64, Error> {
+    let mut result = 0.0;
+    for statement in split_top_level(&input, ';') {
+        if statement.trim().is_empty() {
+            continue;
+        }
+        let (value, target) = evaluate_statement(statement, definitions, functions, options)?;
+        if let Some(name) = target {
+            assignments.push((name, value));
+        }
+        result = value;
+    }
+    Ok(result)
+}
+
+/// evaluates a single statement of a program, returning its value and the variable it was assigned to, if any
+fn evaluate_statement(statement: &str, definitions: &mut Definitions, functions: &Functions, options: &SolveOptions) -> Result<(f64, Option<String>), Error> {
+    use lex::{Symbol, Token};
+
+    let mut reader = InputReader::new(statement);
+    let mut tokens = lex::lex(&mut reader, true, options)?;
+    let (target, compound) = match tokens.as_slice() {
+        [Token::Identifier(name), Token::Symbol(Symbol::Assign), ..] => (Some(name.to_string()), None),
+        [Token::Identifier(name), Token::Symbol(Symbol::CompoundAssign(op)), ..] => (Some(name.to_string()), Some(op.clone())),
+        _ => (None, None),
+    };
+
+    if let Some(name) = &target {
+        tokens.drain(..2);
+        if tokens.is_empty() {
+            return Err(Error::UnexpectedEOI);
+        }
+        // `name op= expression` is evaluated as `name op (expression)`
+        if let Some(op) = compound {
+            if !definitions.exists(name.as_str()) {
+                return Err(Error::UndefinedVariable { name: name.to_string() });
+            }
+            let mut desugared = vec![Token::Identifier(name.to_string()), Token::Operator(op), Token::Symbol(Symbol::LeftParen)];
+            desugared.append(&mut tokens);
+            desugared.push(Token::Symbol(Symbol::RightParen));
+            tokens = desugared;
+        }
+    }
+
+    let shunted = postfix::shunting_yard(&mut tokens, options)?;
+    let result = interpret_with_definitions(&shunted, Some(definitions), Some(functions), options, 0)?;
+    if let Some(name) = &target {
+        definitions.register(name.as_str(), result);
+    }
+    Ok((result, target))
+}
+
+/// splits the input on each `separator` that isn't inside of parentheses
+fn split_top_level(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c == separator && depth <= 0 => {
+                parts.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+/// Evaluates an expression like `evaluate_with_defined`, calling `resolver` for the value of each variable
+/// that isn't in the definitions. This lets values be looked up on demand (i.e. from a spreadsheet)
+/// instead of adding every possible variable to the definitions first.
+///
+/// The definitions are always checked first, so the resolver is only called for variables that aren't defined,
+/// each time one is looked up while evaluating. If the resolver returns `None`, `Error::UndefinedVariable` is returned.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::evaluate_with_resolver;
+///
+/// // cells are named by their row, i.e. c4, and hold twice their row
+/// let resolver = |name: &str| name.strip_prefix('c')?.parse::<f64>().ok().map(|row| row * 2.0);
+/// assert_eq!(evaluate_with_resolver("c4 + c10", None, None, resolver), Ok(28.0));
+/// ```
+pub fn evaluate_with_resolver<S: Into<String>, R: Fn(&str) -> Option<f64>>(input: S, definitions: Option<&Definitions>, functions: Option<&Functions>, resolver: R) -> Result<f64, Error> {
+    let options = SolveOptions::default();
+    let mut input = InputReader::new(input.into());
+    let mut tokens = lex::lex(&mut input, true, &options)?;
+    let shunted = postfix::shunting_yard(&mut tokens, &options)?;
+    let scope = Scope { definitions, functions, depth: 0, resolver: Some(&resolver) };
+    interpret_reusing(&shunted, &shunted.jumps(), scope, &options, &mut EvalState::default(), &mut Scratch::default())
+}
+
+/// Evaluates an expression using only integer arithmetic, so results are exact for any value that fits in an `i128`.
+/// Division truncates toward zero, and decimal numbers are not accepted.
+/// If any operation overflows, `Error::Overflow` is returned instead of losing precision.
+/// Both sides of `&&` and `||` are always evaluated, so an error on either side is returned.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{evaluate_int, Error};
+///
+/// assert_eq!(evaluate_int("9223372036854775807 * 4 + 3"), Ok(36893488147419103231));
+/// assert_eq!(evaluate_int("7 / 2"), Ok(3));
+/// assert_eq!(evaluate_int("2 ^ 127"), Err(Error::Overflow));
+/// ```
+pub fn evaluate_int<S: Into<String>>(input: S) -> Result<i128, Error> {
+    evaluate_int_with_options(input, &SolveOptions::default())
+}
+
+/// Evaluates an expression using only integer arithmetic with the given options,
+/// where `options.overflow_mode` decides what happens when an operation overflows.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{evaluate_int_with_options, OverflowMode, SolveOptions};
+///
+/// let options = SolveOptions { overflow_mode: OverflowMode::Saturating, ..Default::default() };
+/// assert_eq!(evaluate_int_with_options("2 ^ 127", &options), Ok(i128::MAX));
+/// ```
+pub fn evaluate_int_with_options<S: Into<String>>(input: S, options: &SolveOptions) -> Result<i128, Error> {
+    let mut input = InputReader::new(input.into());
+    let mut tokens = lex::lex(&mut input, false, options)?;
+    let mut shunted = postfix::shunting_yard(&mut tokens, options)?;
+    interpret_int(&mut shunted, options)
+}
+
+/// Parses an expression into an expression tree without evaluating it.
+/// Identifiers and function calls are always allowed, and are only resolved when evaluating the tree.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{parse, Definitions};
+///
+/// let ast = parse("2 + 3 * x").unwrap();
+///
+/// let mut defs = Definitions::new();
+/// defs.register("x", 4);
+/// assert_eq!(ast.eval(Some(&defs), None).unwrap(), 14.0);
+/// ```
+pub fn parse<S: Into<String>>(input: S) -> Result<Ast, Error> {
+    let options = SolveOptions::default();
+    let mut input = InputReader::new(input.into());
+    let mut tokens = lex::lex(&mut input, true, &options)?;
+    let mut shunted = postfix::shunting_yard(&mut tokens, &options)?;
+    ast::build_ast(&mut shunted)
+}
+
+/// Formats an expression into a canonical form with consistent spacing
+/// and only the parentheses required by the order of operations.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::format_expr;
+///
+/// assert_eq!(format_expr("1+2 *3").unwrap(), "1 + 2 * 3");
+/// assert_eq!(format_expr("((1 + 2)) * (3)").unwrap(), "(1 + 2) * 3");
+/// ```
+pub fn format_expr<S: Into<String>>(input: S) -> Result<String, Error> {
+    Ok(parse(input)?.to_string())
+}
+
+/// Finds the names of all variables and functions referenced by an expression, without evaluating it.
+/// Returns the sorted and deduplicated variable names and function names, in that order.
+///
+/// This can be used to find which definitions an expression needs before evaluating it.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::free_variables;
+///
+/// let (variables, functions) = free_variables("a + f(b, c) * a").unwrap();
+/// assert_eq!(variables, vec!["a", "b", "c"]);
+/// assert_eq!(functions, vec!["f"]);
+/// ```
+pub fn free_variables<S: Into<String>>(input: S) -> Result<(Vec<String>, Vec<String>), Error> {
+    let mut input = InputReader::new(input.into());
+    let tokens = lex::lex(&mut input, true, &SolveOptions::default())?;
+    let mut variables = BTreeSet::new();
+    let mut functions = BTreeSet::new();
+    lex::collect_identifiers(&tokens, &mut variables, &mut functions);
+    Ok((variables.into_iter().collect(), functions.into_iter().collect()))
+}
+
+/// Whether an expression can be parsed, see `try_parse`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseStatus {
+    /// The expression is complete and can be parsed
+    Complete,
+    /// The expression isn't complete yet, but more input could make it valid (i.e. `1 +` or `(1 + 2`)
+    Incomplete,
+    /// The expression is invalid, and more input won't fix it
+    Error(Error),
+}
+
+/// Checks whether an expression can be parsed while it is still being written, without evaluating it.
+/// This lets an interactive front-end tell input that is still being typed apart from input that is already wrong.
+/// Variables and function calls are allowed, and empty input is incomplete.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{try_parse, Error, ParseStatus};
+///
+/// assert_eq!(try_parse("1 + 2"), ParseStatus::Complete);
+/// assert_eq!(try_parse("(1 + 2"), ParseStatus::Incomplete);
+/// assert_eq!(try_parse("1 + @"), ParseStatus::Error(Error::InvalidCharacter { c: '@' }));
+/// ```
+pub fn try_parse(input: &str) -> ParseStatus {
+    if input.trim().is_empty() {
+        return ParseStatus::Incomplete;
+    }
+    let options = SolveOptions::default();
+    let mut reader = InputReader::new(input);
+    let parsed = lex::lex(&mut reader, true, &options).and_then(|mut tokens| postfix::shunting_yard(&mut tokens, &options));
+    match parsed {
+        Ok(_) => ParseStatus::Complete,
+        // the input ended where more was expected, or with a group that can still be closed.
+        // A group closed while another one inside it is still open, i.e. `(1|`, can't be fixed
+        Err(Error::UnexpectedEOI)
+        | Err(Error::MismatchedParentheses { found: '(', missing: ')' })
+        | Err(Error::MismatchedParentheses { found: '|', missing: '|' }) => ParseStatus::Incomplete,
+        Err(e) => ParseStatus::Error(e),
+    }
+}
+
+/// Splits an expression into tokens without evaluating it, returning the kind and location of each one.
+/// Function calls are split into the function name, parentheses, arguments and separators.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{tokenize, Operator, TokenKind};
+///
+/// let tokens = tokenize("x + 2").unwrap();
+/// assert_eq!(tokens[1].kind, TokenKind::Operator(Operator::Add));
+/// assert_eq!(tokens[1].span, 2..3);
+/// ```
+pub fn tokenize(input: &str) -> Result<Vec<PublicToken>, Error> {
+    let mut input = InputReader::recording(input);
+    lex::lex(&mut input, true, &SolveOptions::default())?;
+    Ok(input.take_spans())
+}
+
+/// Parses an expression into postfix (reverse polish) notation, the order its operands and operators
+/// are evaluated in, for evaluating it with another stack machine. Variables and function calls are allowed.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{to_postfix, Operator, PostfixItem, Value};
+///
+/// let items = to_postfix("2 + max(x, 3)").unwrap();
+/// assert_eq!(items, vec![
+///     PostfixItem::Operand(Value::Int(2)),
+///     PostfixItem::Variable("x".to_string()),
+///     PostfixItem::Operand(Value::Int(3)),
+///     PostfixItem::Function("max".to_string(), 2),
+///     PostfixItem::Operator(Operator::Add),
+/// ]);
+/// ```
+pub fn to_postfix(input: &str) -> Result<Vec<PostfixItem>, Error> {
+    let options = SolveOptions::default();
+    let mut input = InputReader::new(input.to_string());
+    let mut tokens = lex::lex(&mut input, true, &options)?;
+    Ok(postfix::shunting_yard(&mut tokens, &options)?.to_items())
+}
+
+/// Evaluates an expression like `evaluate`, returning the tokens that were lexed and where the
+/// evaluation stopped along with the error if it fails. This is useful for finding out why an input
+/// doesn't evaluate as expected, i.e. when reporting a bug.
+///
+/// # Usage Example:
+/// ```
+/// use calc_lib::{evaluate_checked, Error, Stage};
+///
+/// assert_eq!(evaluate_checked("1 + 2"), Ok(3.0));
+///
+/// let diagnostics = evaluate_checked("1 + 2 $ 3").unwrap_err();
+/// assert_eq!(diagnostics.error, Error::InvalidCharacter { c: '$' });
+/// assert_eq!(diagnostics.stage, Stage::Lexing);
+/// assert_eq!(diagnostics.tokens.len(), 3);
+/// assert_eq!(diagnostics.position, 6);
+/// ```
+pub fn evaluate_checked(input: &str) -> Result<f64, Diagnostics> {
+    let options = SolveOptions::default();
+    let mut reader = InputReader::recording(input);
+    let lexed = lex::lex(&mut reader, false, &options);
+    let position = reader.position();
+    let tokens = reader.take_spans();
+    let diagnostics = |error, stage| Diagnostics { error, stage, tokens: tokens.clone(), position };
+
+    let mut lexed = lexed.map_err(|e| diagnostics(e, Stage::Lexing))?;
+    let mut shunted = postfix::shunting_yard(&mut lexed, &options).map_err(|e| diagnostics(e, Stage::Parsing))?;
+    interpret(&mut shunted, &options).map_err(|e| diagnostics(e, Stage::Evaluating))
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use std::{println, vec};
+    use super::*;
+    #[test]
+    #[allow(clippy::unnecessary_unwrap)]
+    fn test1() {
+        let expression = "(2 + 1) - 50 * 12 / 18 - (3 + 1) * 5";
+
+        // (2 + 1) - 50 * 12 / 18 - (3 + 1) * 5
+        // 3 - 33.333333 - 4 * 5
+        // 3 - 33.333333 - 20
+        // 3 - 53.333333
+        // 50.333333
+
+        let eval = evaluate(expression);
+        if eval.is_err() {
+            panic!("Encountered an error evaluating: {}", eval.unwrap_err());
+        }
+        println!("{}", eval.unwrap());
+    }
+
+    #[test]
+    fn associativity() {
+        assert_eq!(evaluate("8 - 3 - 2"), Ok(3.0));
+        assert_eq!(evaluate("10 - 4 + 1"), Ok(7.0));
+        assert_eq!(evaluate("16 / 4 / 2"), Ok(2.0));
+        assert_eq!(evaluate("12 / 3 * 2"), Ok(8.0));
+        assert_eq!(evaluate("2 ^ 3 ^ 2"), Ok(512.0));
+    }
+
+    #[test]
+    fn underscore_identifiers() {
+        let mut defs = Definitions::new();
+        defs.register("my_var", 2);
+        defs.register("x_1", 3);
+
+        let eval = evaluate_with_defined("my_var + 1", Some(&defs), None);
+        assert_eq!(eval, Ok(3.0));
+        let eval = evaluate_with_defined("x_1 * my_var", Some(&defs), None);
+        assert_eq!(eval, Ok(6.0));
+    }
+
+    #[test]
+    fn underscore_function_names() {
+        let mut funcs = Functions::new();
+        funcs.register("clamp_to", |args| {
+            if args.len() != 2 {
+                return Err(Error::arg_count("clamp_to", 2, args.len()));
+            }
+            Ok(args[0].min(args[1]))
+        });
+
+        let eval = evaluate_with_defined("clamp_to(7, 5)", None, Some(&funcs));
+        assert_eq!(eval, Ok(5.0));
+    }
+
+    #[test]
+    fn variadic_functions() {
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_defined("sum(1, 2, 3, 4)", None, Some(&funcs)), Ok(10.0));
+        assert_eq!(evaluate_with_defined("avg(2, 4)", None, Some(&funcs)), Ok(3.0));
+        assert_eq!(evaluate_with_defined("sum()", None, Some(&funcs)), Err(Error::arg_count("sum", 1, 0)));
+    }
+
+    #[test]
+    fn modulo_modes() {
+        let truncated = SolveOptions::default();
+        assert_eq!(evaluate_with_options("(0 - 7) % 3", None, None, &truncated), Ok(-1.0));
+
+        let euclidean = SolveOptions { modulo_mode: ModuloMode::Euclidean, ..Default::default() };
+        assert_eq!(evaluate_with_options("(0 - 7) % 3", None, None, &euclidean), Ok(2.0));
+        assert_eq!(evaluate_with_options("7.5 % 2", None, None, &euclidean), Ok(1.5));
+    }
+
+    #[test]
+    fn min_max() {
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_defined("max(1, 3, 2)", None, Some(&funcs)), Ok(3.0));
+        assert_eq!(evaluate_with_defined("min(4, -1, 2)", None, Some(&funcs)), Ok(-1.0));
+    }
+
+    #[test]
+    fn decimal_comma() {
+        let options = SolveOptions { decimal_comma: true, ..Default::default() };
+        let funcs = Functions::default();
+        let eval = evaluate_with_options("3,14 + 1", None, None, &options).unwrap();
+        assert!((eval - 4.14).abs() < 1e-9);
+        assert_eq!(evaluate_with_options("max(1; 2; 3)", None, Some(&funcs), &options), Ok(3.0));
+        assert_eq!(evaluate_with_options("max(1,5; 0,5)", None, Some(&funcs), &options), Ok(1.5));
+        assert!(evaluate_with_options("3.14", None, None, &options).is_err());
+
+        // the default convention is unchanged
+        assert_eq!(evaluate_with_defined("max(1, 2.5)", None, Some(&funcs)), Ok(2.5));
+    }
+
+    #[test]
+    fn if_function() {
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_defined("if(1, 10, 20)", None, Some(&funcs)), Ok(10.0));
+        assert_eq!(evaluate_with_defined("if(0, 10, 20)", None, Some(&funcs)), Ok(20.0));
+        assert_eq!(evaluate_with_defined("if(1, 2)", None, Some(&funcs)), Err(Error::arg_count("if", 3, 2)));
+
+        // the arguments can be any expression
+        let mut defs = Definitions::new();
+        defs.register("x", 2);
+        assert_eq!(evaluate_with_defined("if(x, 10, 20)", Some(&defs), Some(&funcs)), Ok(10.0));
+        assert_eq!(evaluate_with_defined("if(x > 1, 10, 20)", Some(&defs), Some(&funcs)), Ok(10.0));
+        assert_eq!(evaluate_with_defined("if(x < 1 || x > 5, 10, -x * (3 + 1))", Some(&defs), Some(&funcs)), Ok(-8.0));
+        assert_eq!(evaluate_with_defined("if(|x - 3| == 1, max(x, 4), 0)", Some(&defs), Some(&funcs)), Ok(4.0));
+        assert_eq!(evaluate_with_defined("2 * if(sqrt(16) == 2 * x, 1, 2) + 1", Some(&defs), Some(&funcs)), Ok(3.0));
+        assert_eq!(evaluate_with_defined("if(x >, 10, 20)", Some(&defs), Some(&funcs)), Err(Error::UnexpectedEOI));
+        assert_eq!(evaluate_with_defined("if(x > 1, 10", Some(&defs), Some(&funcs)), Err(Error::MismatchedParentheses { found: '(', missing: ')' }));
+        assert_eq!(evaluate_with_defined("max(x + 1, 2)", Some(&defs), Some(&funcs)), Ok(3.0));
+        assert_eq!(evaluate_with_defined("max(-x, 1)", Some(&defs), Some(&funcs)), Ok(1.0));
+        assert_eq!(evaluate_with_defined("min(2 * x, sqrt(x + 7))", Some(&defs), Some(&funcs)), Ok(3.0));
+
+        let expr = Expression::compile("if(x > 1, x * 2, 0)").unwrap();
+        assert_eq!(expr.eval(Some(&defs), Some(&funcs)), Ok(4.0));
+        assert_eq!(expr.substitute(&defs).eval(None, Some(&funcs)), Ok(4.0));
+        assert_eq!(expr.stats(), ExprStats { num_operands: 5, num_operators: 2, num_functions: 1, max_paren_depth: 0 });
+        assert_eq!(free_variables("if(x > y, 1, z)"), Ok((vec!["x".to_string(), "y".to_string(), "z".to_string()], vec!["if".to_string()])));
+        assert_eq!(format_expr("if(x>1,x*2,0)"), Ok("if(x > 1, x * 2, 0)".to_string()));
+        assert_eq!(parse("if(x > 1, 10, 20)").unwrap().eval(Some(&defs), Some(&funcs)), Ok(10.0));
+        assert_eq!(to_postfix("if(x > 1, 10, 20)"), Ok(vec![
+            PostfixItem::Variable("x".to_string()), PostfixItem::Operand(Value::Int(1)), PostfixItem::Operator(Operator::Greater),
+            PostfixItem::Operand(Value::Int(10)), PostfixItem::Operand(Value::Int(20)), PostfixItem::Function("if".to_string(), 3),
+        ]));
+        assert_eq!(try_parse("if(x >"), ParseStatus::Incomplete);
+        // a call in the branch that isn't chosen is still made
+        assert_eq!(evaluate_with_defined("if(0, 10, sqrt(y))", Some(&defs), Some(&funcs)), Err(Error::UndefinedVariable { name: "y".to_string() }));
+    }
+
+    #[test]
+    fn clamp_and_sign() {
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_defined("clamp(5, 0, 3)", None, Some(&funcs)), Ok(3.0));
+        assert_eq!(evaluate_with_defined("clamp(-1, 0, 3)", None, Some(&funcs)), Ok(0.0));
+        assert_eq!(evaluate_with_defined("clamp(2, 0, 3)", None, Some(&funcs)), Ok(2.0));
+        assert!(matches!(evaluate_with_defined("clamp(2, 3, 0)", None, Some(&funcs)), Err(Error::InvalidArgument { .. })));
+
+        assert_eq!(evaluate_with_defined("sign(-7)", None, Some(&funcs)), Ok(-1.0));
+        assert_eq!(evaluate_with_defined("sign(2.5)", None, Some(&funcs)), Ok(1.0));
+        assert_eq!(evaluate_with_defined("sign(0)", None, Some(&funcs)), Ok(0.0));
+        assert_eq!(evaluate_with_defined("sign(+3)", None, Some(&funcs)), Ok(1.0));
+    }
+
+    #[test]
+    fn unary_plus() {
+        assert_eq!(evaluate("+5"), Ok(5.0));
+        assert_eq!(evaluate("3 * +2"), Ok(6.0));
+        assert_eq!(evaluate("-+3"), Ok(-3.0));
+        assert_eq!(evaluate("2 - +3"), Ok(-1.0));
+    }
+
+    #[test]
+    fn chained_unary_minus() {
+        assert_eq!(evaluate("--5"), Ok(5.0));
+        assert_eq!(evaluate("---5"), Ok(-5.0));
+        assert_eq!(evaluate("----5"), Ok(5.0));
+        assert_eq!(evaluate("3 - -2"), Ok(5.0));
+        assert_eq!(evaluate("3 - - 2"), Ok(5.0));
+    }
+
+    #[test]
+    fn negated_groups() {
+        assert_eq!(evaluate("-(3)"), Ok(-3.0));
+        assert_eq!(evaluate("-(-(3))"), Ok(3.0));
+        assert_eq!(evaluate("2 * -(1 + 2)"), Ok(-6.0));
+        assert_eq!(evaluate("-(1 + 2) * 2"), Ok(-6.0));
+
+        let mut defs = Definitions::new();
+        defs.register("x", 4);
+        assert_eq!(evaluate_with_defined("-x + 1", Some(&defs), None), Ok(-3.0));
+        assert_eq!(evaluate_with_defined("--x", Some(&defs), None), Ok(4.0));
+        assert_eq!(evaluate_with_defined("-sqrt(x)", Some(&defs), Some(&Functions::default())), Ok(-2.0));
+    }
+
+    #[test]
+    fn percent_mode() {
+        let options = SolveOptions { percent_mode: true, ..Default::default() };
+        assert_eq!(evaluate_with_options("50%", None, None, &options), Ok(0.5));
+        assert_eq!(evaluate_with_options("200 * 5%", None, None, &options), Ok(10.0));
+        assert_eq!(evaluate_with_options("(20 + 30)% + 1", None, None, &options), Ok(1.5));
+        assert!(evaluate_with_options("10 % 3", None, None, &options).is_err());
+
+        // modulo is unchanged when percent mode is off
+        assert_eq!(evaluate("10 % 3"), Ok(1.0));
+    }
+
+    #[test]
+    fn parse_ast() {
+        let ast = parse("2 + 3 * x").unwrap();
+        assert_eq!(ast, Ast::BinaryOp {
+            op: Operator::Add,
+            left: Box::new(Ast::Num(2.0)),
+            right: Box::new(Ast::BinaryOp {
+                op: Operator::Mul,
+                left: Box::new(Ast::Num(3.0)),
+                right: Box::new(Ast::Var("x".to_string())),
+            }),
+        });
+
+        let ast = parse("-sqrt(x)").unwrap();
+        assert_eq!(ast, Ast::UnaryOp {
+            op: Operator::Neg,
+            operand: Box::new(Ast::Call { name: "sqrt".to_string(), args: vec![Ast::Var("x".to_string())] }),
+        });
+    }
+
+    #[test]
+    fn eval_ast() {
+        let mut defs = Definitions::new();
+        defs.register("x", 16);
+        let funcs = Functions::default();
+
+        let ast = parse("(1 + 2) * log(2, x)").unwrap();
+        assert_eq!(ast.eval(Some(&defs), Some(&funcs)), Ok(12.0));
+        assert_eq!(ast.eval(None, Some(&funcs)), Err(Error::UndefinedVariable { name: "x".to_string() }));
+        assert_eq!(ast.eval(Some(&defs), None), Err(Error::UndefinedFunction { name: "log".to_string() }));
+
+        // a tree built by hand can put an operator where it doesn't take that many operands
+        let neg = Ast::BinaryOp { op: Operator::Neg, left: Box::new(Ast::num(1.0)), right: Box::new(Ast::num(2.0)) };
+        assert_eq!(neg.eval(None, None), Err(Error::InvalidOperator { op: "-".to_string() }));
+        let add = Ast::UnaryOp { op: Operator::Add, operand: Box::new(Ast::num(1.0)) };
+        assert_eq!(add.eval(None, None), Err(Error::InvalidOperator { op: "+".to_string() }));
+    }
+
+    #[test]
+    fn compiled_expression() {
+        let expr = Expression::compile("2 * (3 + 4)").unwrap();
+        assert_eq!(expr.debug_postfix(), "2 3 4 + *");
+        assert_eq!(expr.eval(None, None), Ok(14.0));
+
+        let expr = Expression::compile("-x ^ 2 + sqrt(y)").unwrap();
+        assert_eq!(expr.debug_postfix(), "x - 2 ^ sqrt(...) +");
+
+        let funcs = Functions::default();
+        let mut defs = Definitions::new();
+        defs.register("y", 9);
+        for x in 1..4 {
+            defs.register("x", x);
+            assert_eq!(expr.eval(Some(&defs), Some(&funcs)), Ok((x * x + 3) as f64));
+        }
+    }
+
+    #[test]
+    fn trailing_operators() {
+        assert_eq!(evaluate("3 +"), Err(Error::UnexpectedEOI));
+        assert_eq!(evaluate("3 + 4 *"), Err(Error::UnexpectedEOI));
+        assert_eq!(evaluate("-"), Err(Error::UnexpectedEOI));
+        assert_eq!(evaluate("* 3"), Err(Error::InvalidLeadingOperator { op: "*".to_string() }));
+        assert_eq!(evaluate("(3 +) * 2"), Err(Error::Expected { expected: "operand".to_string(), found: ")".to_string() }));
+    }
+
+    #[test]
+    fn free_variable_names() {
+        let (variables, functions) = free_variables("a + f(b, c) * a").unwrap();
+        assert_eq!(variables, vec!["a", "b", "c"]);
+        assert_eq!(functions, vec!["f"]);
+
+        let (variables, functions) = free_variables("sqrt(log(2, x)) + 1").unwrap();
+        assert_eq!(variables, vec!["x"]);
+        assert_eq!(functions, vec!["log", "sqrt"]);
+
+        assert_eq!(free_variables("1 + 2"), Ok((vec![], vec![])));
+    }
+
+    #[test]
+    fn calculator_defined_functions() {
+        let mut calc = Calculator::new();
+        calc.define_function("sq(x) = x*x").unwrap();
+        assert_eq!(calc.eval("sq(5)"), Ok(25.0));
+
+        // defined functions can use other functions and the calculator's definitions
+        calc.definitions_mut().register("offset", 11);
+        calc.define_function("sum_sq(a, b) = sq(a) + sq(b) + offset").unwrap();
+        assert_eq!(calc.eval("sqrt(sum_sq(3, 4)) * 2"), Ok(12.0));
+
+        assert_eq!(calc.eval("sq(1, 2)"), Err(Error::arg_count("sq", 1, 2)));
+        assert!(calc.define_function("sq(2) = 4").is_err());
+        assert!(calc.define_function("x * x").is_err());
+    }
+
+    #[test]
+    fn integer_overflow() {
+        assert_eq!(evaluate_int("9223372036854775807 + 1"), Ok(9223372036854775808));
+        assert_eq!(evaluate_int("170141183460469231731687303715884105727 + 1"), Err(Error::Overflow));
+        assert_eq!(evaluate_int("-170141183460469231731687303715884105727 - 2"), Err(Error::Overflow));
+        assert_eq!(evaluate_int("18446744073709551616 * 18446744073709551616"), Err(Error::Overflow));
+        assert_eq!(evaluate_int("2 ^ 126 + (2 ^ 126 - 1)"), Ok(i128::MAX));
+        assert_eq!(evaluate_int("10 / 0"), Err(Error::DivByZero));
+        assert!(matches!(evaluate_int("1.5 + 1"), Err(Error::InvalidNumber { .. })));
+    }
+
+    #[test]
+    fn operator_precedence() {
+        assert!(Operator::Mul.precedence() > Operator::Add.precedence());
+        assert!(Operator::Pow.precedence() > Operator::Div.precedence());
+        assert_eq!(Operator::Comma.precedence(), None);
+        assert_eq!(Operator::Pow.associativity(), Associativity::Right);
+        assert_eq!(Operator::Sub.associativity(), Associativity::Left);
+        assert_eq!(Operator::Comma.associativity(), Associativity::None);
+    }
+
+    #[test]
+    fn tokenize_spans() {
+        let tokens = tokenize("sin(2) + x").unwrap();
+        let expected = vec![
+            (TokenKind::FunctionName, 0..3),
+            (TokenKind::Paren, 3..4),
+            (TokenKind::Number, 4..5),
+            (TokenKind::Paren, 5..6),
+            (TokenKind::Operator(Operator::Add), 7..8),
+            (TokenKind::Identifier, 9..10),
+        ];
+        assert_eq!(tokens, expected.into_iter().map(|(kind, span)| PublicToken { kind, span }).collect::<Vec<_>>());
+
+        let tokens = tokenize("max(1.5, -2) ÷ y").unwrap();
+        assert_eq!(tokens[2], PublicToken { kind: TokenKind::Number, span: 4..7 });
+        assert_eq!(tokens[3], PublicToken { kind: TokenKind::Separator, span: 7..8 });
+        assert_eq!(tokens[4], PublicToken { kind: TokenKind::Operator(Operator::Sub), span: 9..10 });
+        assert_eq!(tokens[7], PublicToken { kind: TokenKind::Operator(Operator::Div), span: 13..15 });
+        assert_eq!(tokens[8], PublicToken { kind: TokenKind::Identifier, span: 16..17 });
+        assert!(tokenize("").unwrap().is_empty());
+
+        let tokens = tokenize("x = (1)").unwrap();
+        assert_eq!(tokens[1], PublicToken { kind: TokenKind::Assign, span: 2..3 });
+        assert_eq!(tokens[2], PublicToken { kind: TokenKind::Paren, span: 4..5 });
+    }
+
+    #[test]
+    fn unicode_operators() {
+        assert_eq!(evaluate("2 × 3"), Ok(6.0));
+        assert_eq!(evaluate("10 − 4"), Ok(6.0));
+        assert_eq!(evaluate("2 · 5"), Ok(10.0));
+        assert_eq!(evaluate("−3 × 2 ÷ 4"), Ok(-1.5));
+        assert_eq!(evaluate("2 * 3 - 1"), Ok(5.0));
+    }
+
+    #[test]
+    fn absolute_value() {
+        assert_eq!(evaluate("|-5|"), Ok(5.0));
+        assert_eq!(evaluate("|3 - 7|"), Ok(4.0));
+        assert_eq!(evaluate("|2| + |-3|"), Ok(5.0));
+        assert_eq!(evaluate("||2 - 5| - 10|"), Ok(7.0));
+        assert_eq!(evaluate("-|-2| * 3"), Ok(-6.0));
+        assert_eq!(evaluate("2 ^ |1 - 3|"), Ok(4.0));
+        assert_eq!(evaluate("|(1 - 4) * 2|"), Ok(6.0));
+        assert_eq!(evaluate("|2 - 3"), Err(Error::MismatchedParentheses { found: '|', missing: '|' }));
+        assert_eq!(evaluate("(|2)|"), Err(Error::MismatchedParentheses { found: ')', missing: '|' }));
+        assert_eq!(evaluate_int("|-7| * 2"), Ok(14));
+        assert_eq!(format_expr("|x-1|*2").unwrap(), "|x - 1| * 2");
+    }
+
+    #[test]
+    fn max_depth() {
+        let funcs = Functions::default();
+        let nested = format!("{}1{}", "sqrt(".repeat(10000), ")".repeat(10000));
+        assert_eq!(evaluate_with_defined(nested, None, Some(&funcs)), Err(Error::DepthExceeded));
+        let nested = format!("{}1{}", "(".repeat(10000), ")".repeat(10000));
+        assert_eq!(evaluate(nested), Err(Error::DepthExceeded));
+        let nested = format!("{}1{}", "sqrt(".repeat(200), ")".repeat(200));
+        assert_eq!(evaluate_with_defined(nested, None, Some(&funcs)), Ok(1.0));
+
+        let options = SolveOptions { max_depth: 2, ..Default::default() };
+        assert_eq!(evaluate_with_options("((1))", None, None, &options), Ok(1.0));
+        assert_eq!(evaluate_with_options("(|(1)|)", None, None, &options), Err(Error::DepthExceeded));
+
+        let mut calc = Calculator::new();
+        calc.define_function("f(x) = f(x)").unwrap();
+        assert_eq!(calc.eval("f(1)"), Err(Error::DepthExceeded));
+    }
+
+    #[test]
+    fn overflow_modes() {
+        let max = "170141183460469231731687303715884105727";
+        let saturating = SolveOptions { overflow_mode: OverflowMode::Saturating, ..Default::default() };
+        assert_eq!(evaluate_int_with_options(format!("{} + 1", max), &saturating), Ok(i128::MAX));
+        assert_eq!(evaluate_int_with_options(format!("-{} - 5", max), &saturating), Ok(i128::MIN));
+        assert_eq!(evaluate_int_with_options("-(3 ^ 200)", &saturating), Ok(-i128::MAX));
+        assert_eq!(evaluate_int_with_options("(-1) ^ 9999999999", &saturating), Ok(-1));
+        assert_eq!(evaluate_int_with_options("2 + 3", &saturating), Ok(5));
+
+        let wrapping = SolveOptions { overflow_mode: OverflowMode::Wrapping, ..Default::default() };
+        assert_eq!(evaluate_int_with_options(format!("{} + 1", max), &wrapping), Ok(i128::MIN));
+        assert_eq!(evaluate_int_with_options("2 ^ 128", &wrapping), Ok(0));
+        assert_eq!(evaluate_int_with_options("3 ^ 5", &wrapping), Ok(243));
+        assert_eq!(evaluate_int_with_options("1 / 0", &wrapping), Err(Error::DivByZero));
+
+        assert_eq!(evaluate_int(format!("{} + 1", max)), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn programs() {
+        let funcs = Functions::default();
+        let mut defs = Definitions::new();
+        assert_eq!(evaluate_program("x = 2; y = 3; x * y", &mut defs, &funcs), Ok(6.0));
+
+        let mut defs = Definitions::new();
+        assert_eq!(evaluate_program("a = 4; b = sqrt(a) + 1;", &mut defs, &funcs), Ok(3.0));
+        assert_eq!(defs.get("a"), Some(&4.0));
+        assert_eq!(defs.get("b"), Some(&3.0));
+        assert_eq!(evaluate_program("a = a * 2; a", &mut defs, &funcs), Ok(8.0));
+        assert_eq!(evaluate_program(" ; ", &mut defs, &funcs), Ok(0.0));
+        assert_eq!(evaluate_program("c = ; 1", &mut defs, &funcs), Err(Error::UnexpectedEOI));
+        assert_eq!(evaluate_program("z + 1", &mut defs, &funcs), Err(Error::UndefinedVariable { name: "z".to_string() }));
+    }
+
+    #[test]
+    fn gcd_lcm() {
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_defined("gcd(12, 18)", None, Some(&funcs)), Ok(6.0));
+        assert_eq!(evaluate_with_defined("lcm(4, 6)", None, Some(&funcs)), Ok(12.0));
+        assert_eq!(evaluate_with_defined("gcd(-12, 8)", None, Some(&funcs)), Ok(4.0));
+        assert_eq!(evaluate_with_defined("gcd(0, 0)", None, Some(&funcs)), Ok(0.0));
+        assert_eq!(evaluate_with_defined("lcm(0, 5)", None, Some(&funcs)), Ok(0.0));
+        assert!(matches!(evaluate_with_defined("gcd(1.5, 3)", None, Some(&funcs)), Err(Error::InvalidArgument { .. })));
+        assert_eq!(evaluate_with_defined("lcm(4)", None, Some(&funcs)), Err(Error::arg_count("lcm", 2, 1)));
+    }
+
+    #[test]
+    fn exact_integers() {
+        // both numbers round to 2^53 as floats
+        assert_eq!(evaluate("9007199254740993 - 9007199254740992"), Ok(1.0));
+        assert_eq!(evaluate("(2 ^ 100 + 1) - 2 ^ 100"), Ok(1.0));
+        assert_eq!(evaluate("(12345678901234567891 * 10) % 7"), Ok(6.0));
+        assert_eq!(evaluate("10 / 5"), Ok(2.0));
+        assert_eq!(evaluate("7 / 2"), Ok(3.5));
+        assert_eq!(evaluate("-7 % 3"), Ok(-1.0));
+        assert_eq!(evaluate("2 ^ 200"), Ok(2f64.powi(200)));
+        assert_eq!(evaluate("2 ^ 0.5 * 2 ^ 0.5").map(|n| n.round()), Ok(2.0));
+        assert_eq!(evaluate("5 % 0"), Err(Error::DivByZero));
+        assert_eq!(evaluate("5 / 0"), Err(Error::DivByZero));
+    }
+
+    #[test]
+    fn resolver() {
+        let resolver = |name: &str| name.strip_prefix('c')?.parse::<f64>().ok().map(|row| row * 2.0);
+        assert_eq!(evaluate_with_resolver("c3 + c10", None, None, resolver), Ok(26.0));
+
+        // definitions take precedence over the resolver
+        let mut defs = Definitions::new();
+        defs.register("c3", 100);
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_resolver("c3 + max(c1, 1)", Some(&defs), Some(&funcs), resolver), Ok(102.0));
+        assert_eq!(evaluate_with_resolver("c3 + x", Some(&defs), None, resolver), Err(Error::UndefinedVariable { name: "x".to_string() }));
+        assert_eq!(evaluate_with_resolver("max(c2, x)", None, Some(&funcs), resolver), Err(Error::UndefinedVariable { name: "x".to_string() }));
+        assert_eq!(evaluate_with_resolver("if(c2 > 3, c5, 0)", None, Some(&funcs), resolver), Ok(10.0));
+
+        // the resolver is only called for the variables that are looked up, as they are reached
+        let looked_up = core::cell::RefCell::new(Vec::new());
+        let recording = |name: &str| {
+            looked_up.borrow_mut().push(name.to_string());
+            resolver(name)
+        };
+        assert_eq!(evaluate_with_resolver("c3 + c1 || c2 && c4", Some(&defs), None, recording), Ok(1.0));
+        assert_eq!(*looked_up.borrow(), vec!["c1".to_string()]);
+    }
+
+    #[test]
+    fn to_string() {
+        assert_eq!(evaluate_to_string("2+2"), "4");
+        assert_eq!(evaluate_to_string("1/0"), Error::DivByZero.to_string());
+        assert_eq!(evaluate_to_string("7 / 2"), "3.5");
+        assert_eq!(evaluate_to_string("   "), "0");
+        assert_eq!(evaluate_to_string("(1 +"), Error::UnexpectedEOI.to_string());
+        assert_eq!(evaluate_to_string("100000000000000000000000000000000000000000 * 2"), "200000000000000000000000000000000000000000");
+    }
+
+    #[test]
+    fn inverse_and_hyperbolic_trig() {
+        let funcs = Functions::default();
+        let eval = |input: &str| evaluate_with_defined(input, None, Some(&funcs)).unwrap();
+        assert!((eval("atan2(1, 1)") - core::f64::consts::FRAC_PI_4).abs() < 1e-12);
+        assert!((eval("atan2(1, -1)") - 3.0 * core::f64::consts::FRAC_PI_4).abs() < 1e-12);
+        assert_eq!(eval("tanh(0)"), 0.0);
+        assert_eq!(eval("sinh(0)"), 0.0);
+        assert_eq!(eval("cosh(0)"), 1.0);
+        assert!((eval("asin(1)") - core::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert_eq!(eval("acos(1)"), 0.0);
+        assert!((eval("atan(1)") - core::f64::consts::FRAC_PI_4).abs() < 1e-12);
+        assert_eq!(evaluate_with_defined("atan2(1)", None, Some(&funcs)), Err(Error::arg_count("atan2", 2, 1)));
+    }
+
+    #[test]
+    fn adjacent_operands() {
+        assert_eq!(evaluate("2 3"), Err(Error::AdjacentOperands { left: "2".to_string(), right: "3".to_string() }));
+        assert_eq!(evaluate("1 + -2 3.5"), Err(Error::AdjacentOperands { left: "-2".to_string(), right: "3.5".to_string() }));
+        assert_eq!(evaluate("|2| 3"), Err(Error::AdjacentOperands { left: "|...|".to_string(), right: "3".to_string() }));
+        assert_eq!(evaluate("(2) 3"), Err(Error::AdjacentOperands { left: "(...)".to_string(), right: "3".to_string() }));
+        assert_eq!(evaluate("(1)(2)"), Err(Error::AdjacentOperands { left: "(...)".to_string(), right: "(...)".to_string() }));
+        let mut defs = Definitions::new();
+        defs.register("x", 1);
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_defined("-x sqrt(4)", Some(&defs), Some(&funcs)), Err(Error::AdjacentOperands { left: "-x".to_string(), right: "sqrt(...)".to_string() }));
+        assert!(evaluate("2 3").unwrap_err().to_string().contains("multiply them (2 * 3)"));
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(evaluate_lines("1 + 1\n2 * 3\r\n10 % 4"), vec![Ok(2.0), Ok(6.0), Ok(2.0)]);
+        assert_eq!(evaluate_lines("2 ^ 3\n  \n(1 +\n5"), vec![Ok(8.0), Err(Error::UnexpectedEOI), Ok(5.0)]);
+        assert!(evaluate_lines("\n\n").is_empty());
+    }
+
+    #[test]
+    fn default_definitions() {
+        let mut defs = Definitions::default();
+        assert!(!defs.exists("x"));
+        defs.register("x", 3);
+        assert_eq!(evaluate_with_defined("x * 2", Some(&defs), None), Ok(6.0));
+    }
+
+    #[test]
+    fn list_definitions() {
+        let mut defs = Definitions::new();
+        defs.register("b", 2);
+        defs.register("a", 1.5);
+        defs.register("c", -3);
+        let mut listed: Vec<(&String, &f64)> = defs.iter().collect();
+        listed.sort_by(|x, y| x.0.cmp(y.0));
+        assert_eq!(listed, vec![(&"a".to_string(), &1.5), (&"b".to_string(), &2.0), (&"c".to_string(), &-3.0)]);
+
+        let mut funcs = Functions::new();
+        funcs.register("g", |_| Ok(0.0));
+        funcs.register("f", |_| Ok(1.0));
+        let mut names: Vec<&String> = funcs.names().collect();
+        names.sort();
+        assert_eq!(names, vec!["f", "g"]);
+    }
+
+    #[test]
+    fn remove_definitions() {
+        let mut defs = Definitions::new();
+        defs.register("x", 2);
+        defs.register("y", 3);
+        assert_eq!(defs.remove("x"), Some(2.0));
+        assert_eq!(defs.remove("x"), None);
+        assert_eq!(evaluate_with_defined("x + y", Some(&defs), None), Err(Error::UndefinedVariable { name: "x".to_string() }));
+        defs.clear();
+        assert_eq!(defs.iter().count(), 0);
+
+        let mut funcs = Functions::default();
+        assert!(funcs.remove("sqrt"));
+        assert!(!funcs.remove("sqrt"));
+        assert_eq!(evaluate_with_defined("sqrt(4)", None, Some(&funcs)), Err(Error::UndefinedFunction { name: "sqrt".to_string() }));
+        funcs.clear();
+        assert!(!funcs.exists("sin"));
+    }
+
+    #[test]
+    fn zero_argument_functions() {
+        let counter = core::cell::Cell::new(0);
+        let mut funcs = Functions::default();
+        funcs.register("zero", |args| {
+            assert!(args.is_empty());
+            Ok(0.0)
+        });
+        // a stateful function like `rand()`, which returns a different value each call
+        funcs.register("next", |_| {
+            counter.set(counter.get() + 1);
+            Ok(counter.get() as f64)
+        });
+        assert_eq!(evaluate_with_defined("zero()", None, Some(&funcs)), Ok(0.0));
+        assert_eq!(evaluate_with_defined("zero( ) + 1", None, Some(&funcs)), Ok(1.0));
+        assert_eq!(evaluate_with_defined("max(zero(), -2)", None, Some(&funcs)), Ok(0.0));
+        assert_eq!(evaluate_with_defined("next() * 10 + next()", None, Some(&funcs)), Ok(12.0));
+        assert_eq!(evaluate_with_defined("sqrt()", None, Some(&funcs)), Err(Error::arg_count("sqrt", 1, 0)));
+        assert_eq!(parse("zero()"), Ok(Ast::Call { name: "zero".to_string(), args: vec![] }));
+    }
+
+    #[test]
+    fn format_results() {
+        let grouped = FormatOptions { thousands_separator: Some(','), decimal_places: Some(2), ..Default::default() };
+        assert_eq!(format_result(1234567.5, &grouped), "1,234,567.50");
+        assert_eq!(format_result(-999.999, &grouped), "-1,000.00");
+        assert_eq!(format_result(12.0, &grouped), "12.00");
+        assert_eq!(format_result(123456.0, &FormatOptions { thousands_separator: Some(','), ..Default::default() }), "123,456");
+
+        let european = FormatOptions { thousands_separator: Some('.'), decimal_places: None, decimal_separator: ',' };
+        assert_eq!(format_result(1234.25, &european), "1.234,25");
+        assert_eq!(format_result(0.5, &FormatOptions::default()), "0.5");
+        assert_eq!(format_result(f64::INFINITY, &grouped), "inf");
+    }
+
+    #[test]
+    fn double_star_power() {
+        assert_eq!(evaluate("2 ** 10"), Ok(1024.0));
+        assert_eq!(evaluate("2 ** 3 ** 2"), Ok(512.0));
+        assert_eq!(evaluate("2 ** 3 ^ 2"), Ok(512.0));
+        assert_eq!(evaluate("3 * 2 ** 2"), Ok(12.0));
+        assert_eq!(evaluate("2 * * 3"), Err(Error::InvalidOperator { op: "*".to_string() }));
+        assert_eq!(tokenize("2**3").unwrap()[1], PublicToken { kind: TokenKind::Operator(Operator::Pow), span: 1..3 });
+    }
+
+    #[test]
+    fn exact_powers() {
+        assert_eq!(evaluate("2 ^ 62 + 1 - 2 ^ 62"), Ok(1.0));
+        // 3 ^ 40 is too large to be exact as a float, which would make the last digit wrong
+        assert_eq!(evaluate("3 ^ 40 % 10"), Ok(1.0));
+        assert_eq!(evaluate_int("3 ^ 40"), Ok(12157665459056928801));
+        // too large for an integer, so it's approximated
+        assert_eq!(evaluate("2 ^ 200"), Ok(2f64.powi(200)));
+        assert_eq!(evaluate_int("2 ^ 200"), Err(Error::Overflow));
+        assert_eq!(evaluate("4 ^ 0.5"), Ok(2.0));
+        assert_eq!(evaluate("2 ^ -1"), Err(Error::NegativeExponent));
+    }
+
+    #[test]
+    fn strict_identifiers() {
+        let strict = SolveOptions { strict_identifiers: true, ..Default::default() };
+        assert_eq!(evaluate_with_options("y + 1", None, None, &strict), Err(Error::UndefinedVariable { name: "y".to_string() }));
+        assert_eq!(evaluate_with_options("-y", None, None, &strict), Err(Error::UndefinedVariable { name: "y".to_string() }));
+        assert_eq!(evaluate_with_options("y", None, None, &strict), Err(Error::UndefinedVariable { name: "y".to_string() }));
+        assert_eq!(evaluate_with_options("2 * f(1)", None, None, &strict), Err(Error::UndefinedFunction { name: "f".to_string() }));
+
+        let mut defs = Definitions::new();
+        defs.register("x", 1);
+        assert_eq!(evaluate_with_options("x + f(x)", Some(&defs), None, &strict), Err(Error::UndefinedFunction { name: "f".to_string() }));
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_options("sqrt(4) + y", None, Some(&funcs), &strict), Err(Error::UndefinedVariable { name: "y".to_string() }));
+        assert_eq!(evaluate_with_options("sqrt(4) + 1", None, Some(&funcs), &strict), Ok(3.0));
+
+        // without strict mode the errors are less specific
+        assert_eq!(evaluate_with_defined("y + 1", None, None), Err(Error::InvalidCharacter { c: 'y' }));
+        assert_eq!(evaluate_with_defined("2 * f(1)", Some(&defs), None), Err(Error::InvalidOperand { op: "f(...)".to_string() }));
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        // '½' is numeric, but can't be parsed as part of a number
+        assert_eq!(evaluate("2½ + 1"), Err(Error::InvalidNumber { found: "2½".to_string() }));
+        assert_eq!(evaluate("1.5½"), Err(Error::InvalidNumber { found: "1.5½".to_string() }));
+        assert_eq!(evaluate("1.2.3"), Err(Error::InvalidNumber { found: "1.2".to_string() }));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(evaluate("0.1 + 0.2 == 0.3"), Ok(1.0));
+        assert_eq!(evaluate("0.1 + 0.2 != 0.3"), Ok(0.0));
+        assert_eq!(evaluate("0.3 == 0.30001"), Ok(0.0));
+        assert_eq!(evaluate("1 + 2 < 2 * 2"), Ok(1.0));
+        assert_eq!(evaluate("3 >= 4"), Ok(0.0));
+        assert_eq!(evaluate("3 ≤ 3"), Ok(1.0));
+        assert_eq!(evaluate("(2 > 1) + (2 ≠ 2)"), Ok(1.0));
+
+        let exact = SolveOptions { float_epsilon: 0.0, ..Default::default() };
+        assert_eq!(evaluate_with_options("0.1 + 0.2 == 0.3", None, None, &exact), Ok(0.0));
+        let loose = SolveOptions { float_epsilon: 0.01, ..Default::default() };
+        assert_eq!(evaluate_with_options("100 == 100.5", None, None, &loose), Ok(1.0));
+        assert_eq!(evaluate_with_options("100 == 102", None, None, &loose), Ok(0.0));
+
+        // integers are compared exactly
+        assert_eq!(evaluate("9007199254740993 == 9007199254740992"), Ok(0.0));
+        assert_eq!(evaluate_int("2 ^ 100 > 2 ^ 99"), Ok(1));
+        assert_eq!(format_expr("(1 + 2)<=x").unwrap(), "1 + 2 <= x");
+
+        // the ordering comparisons agree with the tolerant equality
+        assert_eq!(evaluate("0.1 + 0.2 > 0.3"), Ok(0.0));
+        assert_eq!(evaluate("0.1 + 0.2 < 0.3"), Ok(0.0));
+        assert_eq!(evaluate("0.1 + 0.2 <= 0.3"), Ok(1.0));
+        assert_eq!(evaluate("0.1 + 0.2 >= 0.3"), Ok(1.0));
+        assert_eq!(evaluate("0.3 < 0.30001"), Ok(1.0));
+
+        // infinite values are never within epsilon of a finite one
+        assert_eq!(evaluate("2 ^ 2000 == 1"), Ok(0.0));
+        assert_eq!(evaluate("2 ^ 2000 > 1"), Ok(1.0));
+        assert_eq!(evaluate("2 ^ 2000 == 2 ^ 2001"), Ok(1.0));
+        let special = SolveOptions::builder().allow_special_floats().build();
+        assert_eq!(evaluate_with_options("inf == 5", None, None, &special), Ok(0.0));
+        assert_eq!(evaluate_with_options("inf != 5", None, None, &special), Ok(1.0));
+        assert_eq!(evaluate_with_options("-inf == 0", None, None, &special), Ok(0.0));
+        assert_eq!(evaluate_with_options("-inf <= 0", None, None, &special), Ok(1.0));
+        assert_eq!(evaluate_with_options("nan == nan", None, None, &special), Ok(0.0));
+        assert_eq!(evaluate_with_options("nan >= nan", None, None, &special), Ok(0.0));
+    }
+
+    #[test]
+    fn eval_range() {
+        let expr = Expression::compile("x^2").unwrap();
+        assert_eq!(expr.eval_range("x", 0.0, 3.0, 1.0, None, None), Ok(vec![(0.0, 0.0), (1.0, 1.0), (2.0, 4.0), (3.0, 9.0)]));
+        assert_eq!(expr.eval_range("x", 2.0, 0.0, 1.0, None, None), Ok(vec![(2.0, 4.0), (1.0, 1.0), (0.0, 0.0)]));
+        assert_eq!(expr.eval_range("x", 0.0, 1.0, 0.1, None, None).unwrap().len(), 11);
+        assert!(matches!(expr.eval_range("x", 0.0, 1.0, 0.0, None, None), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(expr.eval_range("x", 0.0, 1e30, 1e-10, None, None), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(expr.eval_range("x", -1e300, 1e300, 1e-300, None, None), Err(Error::InvalidArgument { .. })));
+
+        let expr = Expression::compile("a * x + sqrt(x)").unwrap();
+        let mut defs = Definitions::new();
+        defs.register("a", 2);
+        let funcs = Functions::default();
+        assert_eq!(expr.eval_range("x", 4.0, 9.0, 5.0, Some(&defs), Some(&funcs)), Ok(vec![(4.0, 10.0), (9.0, 21.0)]));
+        assert_eq!(expr.eval_range("x", 1.0, 2.0, 1.0, None, Some(&funcs)), Err(Error::UndefinedVariable { name: "a".to_string() }));
+    }
+
+    #[test]
+    fn roots() {
+        let funcs = Functions::default();
+        let eval = |input: &str| evaluate_with_defined(input, None, Some(&funcs));
+        assert_eq!(eval("cbrt(-8)"), Ok(-2.0));
+        assert_eq!(eval("cbrt(27)"), Ok(3.0));
+        assert_eq!(eval("root(3, 27)"), Ok(3.0));
+        assert_eq!(eval("root(3, -27)"), Ok(-3.0));
+        assert_eq!(eval("root(4, 16)"), Ok(2.0));
+        assert_eq!(eval("root(2, 2)"), Ok(core::f64::consts::SQRT_2));
+        assert!(matches!(eval("root(2, -4)"), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(eval("root(0, 4)"), Err(Error::InvalidArgument { .. })));
+        assert_eq!(eval("root(3)"), Err(Error::arg_count("root", 2, 1)));
+    }
+
+    #[test]
+    fn empty_parentheses() {
+        assert_eq!(evaluate("()"), Err(Error::EmptyParentheses));
+        assert_eq!(evaluate("3 * ( )"), Err(Error::EmptyParentheses));
+        assert_eq!(evaluate("(())"), Err(Error::EmptyParentheses));
+        let mut funcs = Functions::new();
+        funcs.register("f", |_| Ok(2.0));
+        assert_eq!(evaluate_with_defined("3 * f()", None, Some(&funcs)), Ok(6.0));
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let mut calc = Calculator::new();
+        assert_eq!(calc.eval("x = 10"), Ok(10.0));
+        assert_eq!(calc.eval("x += 5"), Ok(15.0));
+        assert_eq!(calc.eval("x -= 1 + 2"), Ok(12.0));
+        assert_eq!(calc.eval("x *= 2"), Ok(24.0));
+        assert_eq!(calc.eval("x /= 4 + 2"), Ok(4.0));
+        assert_eq!(calc.eval("x ^= 2"), Ok(16.0));
+        assert_eq!(calc.eval("x %= 5"), Ok(1.0));
+        assert_eq!(calc.eval("x"), Ok(1.0));
+        assert_eq!(calc.eval("y += 1"), Err(Error::UndefinedVariable { name: "y".to_string() }));
+        assert_eq!(calc.eval("x +="), Err(Error::UnexpectedEOI));
+        assert_eq!(calc.eval("a = 2; a *= a; a"), Ok(4.0));
+        // the operator and the '=' are a single token
+        assert_eq!(calc.eval("x - = 1"), Err(Error::InvalidOperator { op: "=".to_string() }));
+        assert_eq!(calc.eval("x * = 2"), Err(Error::InvalidOperator { op: "=".to_string() }));
+        assert_eq!(calc.eval("x"), Ok(1.0));
+        assert_eq!(calc.eval("2 += 3"), Err(Error::InvalidOperator { op: "+=".to_string() }));
+        assert_eq!(calc.eval("x = 2; x **= 3"), Ok(8.0));
+        assert_eq!(tokenize("x += 1").unwrap()[1], PublicToken { kind: TokenKind::Assign, span: 2..4 });
+    }
+
+    #[test]
+    fn options_builder() {
+        assert_eq!(SolveOptions::builder().build(), SolveOptions::default());
+
+        let options = SolveOptions::builder().euclidean_mod().percent_mode().max_depth(128).build();
+        assert_eq!(options.modulo_mode, ModuloMode::Euclidean);
+        assert_eq!(options.max_depth, 128);
+        assert_eq!(evaluate_with_options("-7 % 3", None, None, &options), Err(Error::AdjacentOperands { left: "-7%".to_string(), right: "3".to_string() }));
+        assert_eq!(evaluate_with_options("50% * 3", None, None, &options), Ok(1.5));
+
+        let options = SolveOptions::builder().decimal_comma().strict_identifiers().float_epsilon(0.0).overflow_mode(OverflowMode::Wrapping).build();
+        assert_eq!(evaluate_with_options("1,5 + x", None, None, &options), Err(Error::UndefinedVariable { name: "x".to_string() }));
+        assert_eq!(options.float_epsilon, 0.0);
+        assert_eq!(options.overflow_mode, OverflowMode::Wrapping);
+
+        let options = SolveOptions::builder().modulo_mode(ModuloMode::Euclidean).build();
+        assert_eq!(evaluate_with_options("-7 % 3", None, None, &options), Ok(2.0));
+    }
+
+    #[test]
+    fn memoize() {
+        let calls = core::cell::Cell::new(0);
+        let mut funcs = Functions::default();
+        funcs.register("f", |args| {
+            calls.set(calls.get() + 1);
+            Ok(args[0] * 2.0)
+        });
+
+        let options = SolveOptions::builder().memoize().build();
+        assert_eq!(evaluate_with_options("f(2) + f(2) * f(3) - max(f(2), 1)", None, Some(&funcs), &options), Ok(24.0));
+        assert_eq!(calls.get(), 2);
+
+        // each evaluation starts without any remembered results
+        assert_eq!(evaluate_with_options("f(2)", None, Some(&funcs), &options), Ok(4.0));
+        assert_eq!(calls.get(), 3);
+
+        calls.set(0);
+        assert_eq!(evaluate_with_defined("f(2) + f(2) * f(3) - max(f(2), 1)", None, Some(&funcs)), Ok(24.0));
+        assert_eq!(calls.get(), 4);
+
+        // calls on the right side of `&&` and `||` use the same results
+        calls.set(0);
+        assert_eq!(evaluate_with_options("f(2) && f(2) + f(3)", None, Some(&funcs), &options), Ok(1.0));
+        assert_eq!(calls.get(), 2);
+        calls.set(0);
+        assert_eq!(evaluate_with_options("0 && f(2)", None, Some(&funcs), &options), Ok(0.0));
+        assert_eq!(calls.get(), 0);
+    }
+
+    #[test]
+    fn capture_assignments() {
+        let funcs = Functions::default();
+        let mut defs = Definitions::new();
+        let captured = evaluate_program_capturing("a = 2; b = a + 3; b", &mut defs, &funcs);
+        assert_eq!(captured, Ok((5.0, vec![("a".to_string(), 2.0), ("b".to_string(), 5.0)])));
+
+        let captured = evaluate_program_capturing("a += 1; a * 2; a = 0", &mut defs, &funcs);
+        assert_eq!(captured, Ok((0.0, vec![("a".to_string(), 3.0), ("a".to_string(), 0.0)])));
+        assert_eq!(evaluate_program_capturing("1 + 1", &mut defs, &funcs), Ok((2.0, vec![])));
+    }
+
+    #[test]
+    fn single_operators() {
+        assert_eq!(evaluate(")"), Err(Error::MismatchedParentheses { found: ')', missing: '(' }));
+        assert_eq!(evaluate(") + 1"), Err(Error::MismatchedParentheses { found: ')', missing: '(' }));
+        assert_eq!(evaluate("("), Err(Error::MismatchedParentheses { found: '(', missing: ')' }));
+        assert_eq!(evaluate("2 * ("), Err(Error::MismatchedParentheses { found: '(', missing: ')' }));
+        assert_eq!(evaluate("|"), Err(Error::MismatchedParentheses { found: '|', missing: '|' }));
+        assert_eq!(evaluate("(1 +"), Err(Error::UnexpectedEOI));
+        for op in ["*", "/", "^", "%", "=", "==", "<", ">=", "**"] {
+            assert_eq!(evaluate(op), Err(Error::InvalidLeadingOperator { op: op.replace("**", "^") }));
+        }
+        assert_eq!(evaluate("+"), Err(Error::UnexpectedEOI));
+        assert_eq!(evaluate("-"), Err(Error::UnexpectedEOI));
+
+        // a unary operator alone in parentheses is missing its operand
+        let expected = Err(Error::Expected { expected: "operand".to_string(), found: ")".to_string() });
+        for input in ["(-)", "(+)", "((-))", "(!)", "(- -)", "2 * (-)"] {
+            assert_eq!(evaluate(input), expected);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reader() {
+        assert_eq!(evaluate_reader(std::io::Cursor::new(b"2 ^ 10 - 24")), Ok(1000.0));
+        assert_eq!(evaluate_reader(std::io::Cursor::new("1 +\n 2\n".as_bytes())), Ok(3.0));
+        assert_eq!(evaluate_reader(std::io::Cursor::new(b"1 / 0")), Err(Error::DivByZero));
+        assert!(matches!(evaluate_reader(std::io::Cursor::new(b"1 + \xff")), Err(Error::Other(_))));
+    }
+
+    #[test]
+    fn build_expressions() {
+        let mut defs = Definitions::new();
+        defs.register("x", 3);
+        let funcs = Functions::default();
+
+        let expr = (Expr::var("x") + 1.0) * 2.0;
+        assert_eq!(expr.eval(Some(&defs), None), Ok(8.0));
+        assert_eq!(expr, parse("(x + 1) * 2").unwrap());
+
+        let expr = -Expr::call("sqrt", vec![Expr::num(16.0)]).pow(Expr::var("x")) / 4.0 - Expr::var("x") % 2.0;
+        assert_eq!(expr.to_string(), "-(sqrt(16) ^ x) / 4 - x % 2");
+        assert_eq!(expr.eval(Some(&defs), Some(&funcs)), Ok(-17.0));
+    }
+
+    #[test]
+    fn variable_function_mismatch() {
+        let mut defs = Definitions::new();
+        defs.register("x", 2);
+        let funcs = Functions::default();
+
+        let not_callable = Err(Error::NotCallable { name: "x".to_string() });
+        assert_eq!(evaluate_with_defined("x(2)", Some(&defs), Some(&funcs)), not_callable);
+        assert_eq!(evaluate_with_defined("1 + x(2)", Some(&defs), None), not_callable);
+        assert_eq!(evaluate_with_defined("sqrt(x(2))", Some(&defs), Some(&funcs)), not_callable);
+        assert_eq!(parse("x(2)").unwrap().eval(Some(&defs), Some(&funcs)), not_callable);
+
+        let not_a_value = Err(Error::NotAValue { name: "sqrt".to_string() });
+        assert_eq!(evaluate_with_defined("sqrt + 1", Some(&defs), Some(&funcs)), not_a_value);
+        assert_eq!(evaluate_with_defined("sqrt + 1", None, Some(&funcs)), not_a_value);
+        assert_eq!(evaluate_with_defined("max(x, sqrt)", Some(&defs), Some(&funcs)), not_a_value);
+        assert_eq!(evaluate_with_defined("max(1, sqrt)", None, Some(&funcs)), not_a_value);
+        assert_eq!(parse("sqrt + 1").unwrap().eval(Some(&defs), Some(&funcs)), not_a_value);
+
+        assert_eq!(evaluate_with_defined("y(2)", Some(&defs), Some(&funcs)), Err(Error::UndefinedFunction { name: "y".to_string() }));
+        assert_eq!(evaluate_with_defined("y + 1", Some(&defs), Some(&funcs)), Err(Error::UndefinedVariable { name: "y".to_string() }));
+    }
+
+    #[test]
+    fn allowed_functions() {
+        let funcs = Functions::default();
+        let options = SolveOptions::builder().allowed_functions(["sqrt", "max"]).build();
+
+        assert_eq!(evaluate_with_options("sqrt(16) + max(1, 2)", None, Some(&funcs), &options), Ok(6.0));
+        assert_eq!(evaluate_with_options("sqrt(16) + sin(0)", None, Some(&funcs), &options), Err(Error::FunctionNotAllowed { name: "sin".to_string() }));
+        assert_eq!(evaluate_with_options("max(1, cos(0))", None, Some(&funcs), &options), Err(Error::FunctionNotAllowed { name: "cos".to_string() }));
+        assert_eq!(evaluate_with_options("undefined(1)", None, Some(&funcs), &options), Err(Error::FunctionNotAllowed { name: "undefined".to_string() }));
+        assert_eq!(parse("sin(0)").unwrap().eval_with_options(None, Some(&funcs), &options), Err(Error::FunctionNotAllowed { name: "sin".to_string() }));
+
+        let none_allowed = SolveOptions::builder().allowed_functions(Vec::<String>::new()).build();
+        assert_eq!(evaluate_with_options("sqrt(16)", None, Some(&funcs), &none_allowed), Err(Error::FunctionNotAllowed { name: "sqrt".to_string() }));
+        assert_eq!(evaluate_with_options("sin(0)", None, Some(&funcs), &SolveOptions::default()), Ok(0.0));
+    }
+
+    #[test]
+    fn max_operations() {
+        let funcs = Functions::default();
+        let options = SolveOptions::builder().max_operations(100).build();
+
+        let nested = format!("{}1{}", "(1 + ".repeat(100), ")".repeat(100));
+        assert_eq!(evaluate_with_options(nested.as_str(), None, None, &options), Ok(101.0));
+        let nested = format!("{}1{}", "(1 + ".repeat(101), ")".repeat(101));
+        assert_eq!(evaluate_with_options(nested.as_str(), None, None, &options), Err(Error::OperationLimitExceeded));
+
+        // function calls count towards the limit as well
+        let nested = format!("{}1{}", "sqrt(".repeat(50), ")".repeat(50));
+        assert_eq!(evaluate_with_options(format!("{} + 1", nested), None, Some(&funcs), &options), Ok(2.0));
+        let nested = format!("{}1{}", "sqrt(".repeat(100), ")".repeat(100));
+        assert_eq!(evaluate_with_options(format!("{} + 1", nested), None, Some(&funcs), &options), Err(Error::OperationLimitExceeded));
+
+        // as do the operators around `&&` and `||`
+        let options = SolveOptions::builder().max_operations(3).build();
+        assert_eq!(evaluate_with_options("1 + 1 + 1 + 1 + 1 + 1 && 1", None, None, &options), Err(Error::OperationLimitExceeded));
+        assert_eq!(evaluate_with_options("(1 && 1) + 1 + 1 + 1 + 1 + 1", None, None, &options), Err(Error::OperationLimitExceeded));
+        assert_eq!(evaluate_with_options("0 && 1 + 1 + 1 + 1", None, None, &options), Ok(0.0));
+
+        // the bodies of defined functions count towards the limit of the expression that calls them
+        let mut calc = Calculator::with_options(SolveOptions::builder().max_operations(5).build());
+        calc.define_function("g(x) = x + 1 + 1 + 1").unwrap();
+        calc.define_function("h(x) = g(x) + g(x)").unwrap();
+        assert_eq!(calc.eval("g(1)"), Ok(4.0));
+        assert_eq!(calc.eval("h(1) + h(1)"), Err(Error::OperationLimitExceeded));
+    }
+
+    #[test]
+    fn expression_stats() {
+        let stats = Expression::compile("sin(x) + 2 * (3 - 1)").unwrap().stats();
+        assert_eq!(stats, ExprStats { num_operands: 4, num_operators: 3, num_functions: 1, max_paren_depth: 1 });
+
+        let stats = Expression::compile("-((1 + max(a, sqrt(b))) * |2|)").unwrap().stats();
+        assert_eq!(stats, ExprStats { num_operands: 4, num_operators: 4, num_functions: 2, max_paren_depth: 2 });
+        assert_eq!(Expression::compile("5").unwrap().stats(), ExprStats { num_operands: 1, ..ExprStats::default() });
+    }
+
+    #[test]
+    fn whitespace_between_arguments() {
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_defined("max(1 , 2 , 3)", None, Some(&funcs)), Ok(3.0));
+        assert_eq!(evaluate_with_defined("max(1\t,\t2\t,3\t)", None, Some(&funcs)), Ok(3.0));
+        assert_eq!(evaluate_with_defined("max(1\n,\n2\r\n, 3\n)", None, Some(&funcs)), Ok(3.0));
+        assert_eq!(evaluate_with_defined("max( 1 \t\r\n, 2 )", None, Some(&funcs)), Ok(2.0));
+    }
+
+    #[test]
+    fn empty_arguments() {
+        let funcs = Functions::default();
+        let expected = |found: &str| Err(Error::Expected { expected: "argument".to_string(), found: found.to_string() });
+        assert_eq!(evaluate_with_defined("max(1, 2,)", None, Some(&funcs)), expected(")"));
+        assert_eq!(evaluate_with_defined("max(1, 2, )", None, Some(&funcs)), expected(")"));
+        assert_eq!(evaluate_with_defined("max(1, , 2)", None, Some(&funcs)), expected(","));
+        assert_eq!(evaluate_with_defined("max(1,, 2)", None, Some(&funcs)), expected(","));
+        assert_eq!(evaluate_with_defined("max(, 1)", None, Some(&funcs)), expected(","));
+        assert_eq!(evaluate_with_options("max(1; 2;)", None, Some(&funcs), &SolveOptions::builder().decimal_comma().build()), expected(")"));
+        assert_eq!(evaluate_with_defined("max()", None, Some(&funcs)), Err(Error::arg_count("max", 1, 0)));
+    }
+
+    #[test]
+    fn div_by_zero_modes() {
+        assert_eq!(evaluate("1 / 0"), Err(Error::DivByZero));
+        assert_eq!(evaluate("1.5 % 0"), Err(Error::DivByZero));
+
+        let nan = SolveOptions::builder().div_by_zero(DivByZeroMode::NaN).build();
+        assert!(evaluate_with_options("1 / 0", None, None, &nan).unwrap().is_nan());
+        assert!(evaluate_with_options("1 % 0", None, None, &nan).unwrap().is_nan());
+        assert_eq!(evaluate_with_options("1 / 2", None, None, &nan), Ok(0.5));
+
+        let infinity = SolveOptions::builder().div_by_zero(DivByZeroMode::Infinity).build();
+        assert_eq!(evaluate_with_options("1 / 0", None, None, &infinity), Ok(f64::INFINITY));
+        assert_eq!(evaluate_with_options("-1 / 0", None, None, &infinity), Ok(f64::NEG_INFINITY));
+        assert_eq!(evaluate_with_options("2 * 1 / 0 + 1", None, None, &infinity), Ok(f64::INFINITY));
+        assert!(evaluate_with_options("0 / 0", None, None, &infinity).unwrap().is_nan());
+        assert!(evaluate_with_options("1 % 0", None, None, &infinity).unwrap().is_nan());
+
+        // integers have no NaN or infinity
+        assert_eq!(evaluate_int_with_options("1 / 0", &infinity), Err(Error::DivByZero));
+    }
+
+    #[test]
+    fn mod_keyword() {
+        let defs = Definitions::new();
+        assert_eq!(evaluate_with_defined("17 mod 5", Some(&defs), None), Ok(2.0));
+        assert_eq!(evaluate_with_defined("17 mod 5 == 2", Some(&defs), None), Ok(1.0));
+        assert_eq!(evaluate_with_defined("2 * 17 mod(5) + 1", Some(&defs), None), Ok(5.0));
+        assert_eq!(evaluate_with_options("-7 mod 3", None, None, &SolveOptions::builder().strict_identifiers().euclidean_mod().build()), Ok(2.0));
+        assert_eq!(tokenize("7 mod 3").unwrap()[1], PublicToken { kind: TokenKind::Operator(Operator::Mod), span: 2..5 });
+
+        // `mod` is only an operator when identifiers are allowed, and can't be a variable
+        assert_eq!(evaluate("17 mod 5"), Err(Error::InvalidCharacter { c: 'm' }));
+        assert_eq!(evaluate_with_defined("mod + 1", Some(&defs), None), Err(Error::InvalidLeadingOperator { op: "%".to_string() }));
+        assert_eq!(evaluate_with_defined("modulo + 1", Some(&defs), None), Err(Error::UndefinedVariable { name: "modulo".to_string() }));
+    }
+
+    #[test]
+    fn integer_division() {
+        assert_eq!(evaluate("17 // 5"), Ok(3.0));
+        assert_eq!(evaluate("17 // 5 == 3"), Ok(1.0));
+        assert_eq!(evaluate("-7 // 2"), Ok(-3.0));
+        assert_eq!(evaluate("7.5 // -2"), Ok(-3.0));
+        assert_eq!(evaluate("2 * 9 // 4"), Ok(4.0));
+        assert_eq!(evaluate("1 + 9 // 2 * 3"), Ok(13.0));
+        assert_eq!(evaluate("1 // 0"), Err(Error::DivByZero));
+        assert_eq!(evaluate_int("-7 // 2"), Ok(-3));
+        assert_eq!(evaluate_int("1 // 0"), Err(Error::DivByZero));
+        assert_eq!(evaluate_with_defined("17 div 5", Some(&Definitions::new()), None), Ok(3.0));
+        assert_eq!(parse("(1 + 9) // 2").unwrap().to_string(), "(1 + 9) // 2");
+
+        let mut defs = Definitions::new();
+        assert_eq!(evaluate_program("x = 17; x //= 5; x", &mut defs, &Functions::new()), Ok(3.0));
+    }
+
+    #[test]
+    fn integer_values() {
+        assert_eq!(evaluate_value("2 + 2"), Ok(Value::Int(4)));
+        assert_eq!(evaluate_value("2.0 + 2"), Ok(Value::Float(4.0)));
+        assert_eq!(evaluate_value("2 ^ 3 // 3 - |-1|"), Ok(Value::Int(1)));
+        assert_eq!(evaluate_value("1 / 4"), Ok(Value::Float(0.25)));
+        assert_eq!(evaluate_value("1 < 2"), Ok(Value::Int(1)));
+        assert_eq!(evaluate_value("2 ^ 200"), Ok(Value::Float(2f64.powi(200))));
+        assert_eq!(evaluate_value("1 / 0"), Err(Error::DivByZero));
+        // a zero right operand only leaves integers for the division operators
+        assert_eq!(evaluate_value("5 + 0"), Ok(Value::Int(5)));
+        assert_eq!(evaluate_value("2 ^ 0 * 3"), Ok(Value::Int(3)));
+        assert_eq!(evaluate_value("1 && 0"), Ok(Value::Int(0)));
+        assert_eq!(evaluate_value("7 // 0"), Err(Error::DivByZero));
+
+        assert_eq!(Value::Int(9).to_string(), "9");
+        assert_eq!(Value::Float(9.0).to_string(), "9.0");
+        assert_eq!(Value::Float(-0.5).to_string(), "-0.5");
+        assert_eq!(Value::Float(f64::INFINITY).to_string(), "inf");
+        assert_eq!(Value::Int(-3).as_f64(), -3.0);
+    }
+
+    #[test]
+    fn substitute() {
+        let funcs = Functions::default();
+        let expr = Expression::compile("a * x + b - max(x, c)").unwrap();
+        let mut defs = Definitions::new();
+        defs.register("a", 2);
+        defs.register("b", 1);
+        defs.register("c", 4);
+        let substituted = expr.substitute(&defs);
+        assert_eq!(substituted.debug_postfix(), "2.0 x * 1.0 + max(...) -");
+
+        let mut x = Definitions::new();
+        x.register("x", 3);
+        assert_eq!(substituted.eval(Some(&x), Some(&funcs)), Ok(3.0));
+        x.register("x", 5);
+        assert_eq!(substituted.eval(Some(&x), Some(&funcs)), Ok(6.0));
+        assert_eq!(substituted.eval(Some(&Definitions::new()), Some(&funcs)), Err(Error::UndefinedVariable { name: "x".to_string() }));
+        // the original expression is unchanged
+        assert_eq!(expr.eval(Some(&x), Some(&funcs)), Err(Error::UndefinedVariable { name: "a".to_string() }));
+    }
+
+    #[test]
+    fn percentages() {
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_defined("percent_of(25, 200)", None, Some(&funcs)), Ok(12.5));
+        assert_eq!(evaluate_with_defined("percent_of(-3, 4)", None, Some(&funcs)), Ok(-75.0));
+        assert_eq!(evaluate_with_defined("percent_of(1, 0)", None, Some(&funcs)), Err(Error::DivByZero));
+        assert_eq!(evaluate_with_defined("pct_change(100, 150)", None, Some(&funcs)), Ok(50.0));
+        assert_eq!(evaluate_with_defined("pct_change(100, 75)", None, Some(&funcs)), Ok(-25.0));
+        assert_eq!(evaluate_with_defined("pct_change(-10, -5)", None, Some(&funcs)), Ok(50.0));
+        assert_eq!(evaluate_with_defined("pct_change(0, 5)", None, Some(&funcs)), Err(Error::DivByZero));
+        assert_eq!(evaluate_with_defined("pct_change(1)", None, Some(&funcs)), Err(Error::arg_count("pct_change", 2, 1)));
+    }
+
+    #[test]
+    fn extend() {
+        let mut funcs = Functions::default();
+        let mut custom = Functions::new();
+        custom.register("double", |args| Ok(args[0] * 2.0));
+        custom.register("sign", |_| Ok(42.0));
+        funcs.extend(custom);
+        assert_eq!(evaluate_with_defined("double(sqrt(16))", None, Some(&funcs)), Ok(8.0));
+        assert_eq!(evaluate_with_defined("sign(-1)", None, Some(&funcs)), Ok(42.0));
+
+        let mut defs = Definitions::new();
+        defs.register("x", 1);
+        defs.register("y", 2);
+        let mut other = Definitions::new();
+        other.register("y", 3);
+        other.register("z", 4);
+        defs.extend(other);
+        assert_eq!(evaluate_with_defined("x + y + z", Some(&defs), None), Ok(8.0));
+    }
+
+    #[test]
+    fn validate_arity() {
+        let mut funcs = Functions::default();
+        funcs.register_with_arity("double", 1, |args| Ok(args[0] * 2.0));
+        funcs.register("any", |args| Ok(args.len() as f64));
+
+        assert_eq!(Expression::compile("sqrt(1, 2)").unwrap().validate(&funcs), Err(Error::arg_count("sqrt", 1, 2)));
+        assert_eq!(Expression::compile("1 + max(2, log(3))").unwrap().validate(&funcs), Err(Error::arg_count("log", 2, 1)));
+        assert_eq!(Expression::compile("double()").unwrap().validate(&funcs), Err(Error::arg_count("double", 1, 0)));
+        assert_eq!(Expression::compile("nope(1)").unwrap().validate(&funcs), Err(Error::UndefinedFunction { name: "nope".to_string() }));
+        assert_eq!(Expression::compile("double(x) + any() + any(1, 2, 3) + sum(1, 2)").unwrap().validate(&funcs), Ok(()));
+
+        // the arity is also checked when a function is called, so `f` doesn't need to check it
+        assert_eq!(evaluate_with_defined("double(1, 2)", None, Some(&funcs)), Err(Error::arg_count("double", 1, 2)));
+        assert_eq!(evaluate_with_defined("double(4)", None, Some(&funcs)), Ok(8.0));
+
+        let mut calc = Calculator::new();
+        calc.define_function("f(a, b) = a + b").unwrap();
+        assert_eq!(Expression::compile("f(1)").unwrap().validate(calc.functions()), Err(Error::arg_count("f", 2, 1)));
+    }
+
+    #[test]
+    fn vectors() {
+        assert_eq!(evaluate_vec("[1+1, 2*3, sqrt(16)]"), Ok(vec![2.0, 6.0, 4.0]));
+        assert_eq!(evaluate_vec("[max(1, 2), log(2, 8)]"), Ok(vec![2.0, 3.0]));
+        assert_eq!(evaluate_vec(" [ (1, 2), 3 ] "), Ok(vec![2.0, 3.0]));
+        assert_eq!(evaluate_vec("[]"), Ok(vec![]));
+        assert_eq!(evaluate_vec("[ ]"), Ok(vec![]));
+        assert_eq!(evaluate_vec("[5]"), Ok(vec![5.0]));
+        assert_eq!(evaluate_vec("[1, 2 / 0]"), Err(Error::DivByZero));
+        assert_eq!(evaluate_vec("[1, [2]]"), Err(Error::InvalidCharacter { c: '[' }));
+        assert_eq!(evaluate_vec("[1, 2,]"), Err(Error::Expected { expected: "expression".to_string(), found: ",".to_string() }));
+        assert_eq!(evaluate_vec("[1, 2"), Err(Error::MismatchedParentheses { found: '[', missing: ']' }));
+        assert_eq!(evaluate_vec("1, 2"), Err(Error::Expected { expected: "[".to_string(), found: "1".to_string() }));
+        assert_eq!(evaluate_vec(""), Err(Error::UnexpectedEOI));
+    }
+
+    #[test]
+    fn fold_constants() {
+        let funcs = Functions::default();
+        let options = SolveOptions::builder().fold_constants().build();
+        let mut defs = Definitions::new();
+        defs.register("x", 4);
+
+        let expr = Expression::compile_with_options("2*3 + x", &options).unwrap();
+        assert_eq!(expr.debug_postfix(), "6 x +");
+        assert_eq!(expr.eval(Some(&defs), None), Ok(10.0));
+
+        let expr = Expression::compile_with_options("-(1 + 1) * x ^ (4 / 2) - sqrt(16) + |-3|", &options).unwrap();
+        assert_eq!(expr.debug_postfix(), "-2 x 2 ^ * sqrt(...) - 3 +");
+        assert_eq!(expr.eval(Some(&defs), Some(&funcs)), Ok(-33.0));
+        assert_eq!(Expression::compile_with_options("(1 + 2) * 3", &options).unwrap().debug_postfix(), "9");
+        assert_eq!(Expression::compile_with_options("1 / 0 + x", &options).unwrap_err(), Error::DivByZero);
+        assert_eq!(Expression::compile("2*3 + x").unwrap().debug_postfix(), "2 3 * x +");
+    }
+
+    #[test]
+    fn constant_expressions() {
+        assert!(Expression::compile("2 + 3").unwrap().is_constant());
+        assert!(Expression::compile("-(2 ^ 3) % 5 == |-3|").unwrap().is_constant());
+        assert!(Expression::compile("").unwrap().is_constant());
+        assert!(!Expression::compile("x + 1").unwrap().is_constant());
+        assert!(!Expression::compile("sqrt(4)").unwrap().is_constant());
+        assert!(!Expression::compile("1 + 2 * max(1, 2)").unwrap().is_constant());
+    }
+
+    #[test]
+    fn negative_zero() {
+        for input in ["0 * -1", "3 - 3", "0.0 * -1", "-0.0", "-(1.5 - 1.5)", "-1 / 2 ^ 2000"] {
+            let result = evaluate(input).unwrap();
+            assert_eq!(result.to_string(), "0", "{}", input);
+            assert_eq!(parse(input).unwrap().eval(None, None).map(|n| n.to_string()), Ok("0".to_string()), "{}", input);
+        }
+        assert_eq!(evaluate_value("0.0 * -1"), Ok(Value::Float(0.0)));
+        assert_eq!(evaluate_value("0.0 * -1").unwrap().to_string(), "0.0");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn custom_errors() {
+        #[derive(Debug, PartialEq)]
+        struct OutOfStock {
+            item: u32,
+        }
+
+        impl Display for OutOfStock {
+            fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+                write!(f, "item {} is out of stock", self.item)
+            }
+        }
+
+        impl std::error::Error for OutOfStock {}
+
+        let mut funcs = Functions::new();
+        funcs.register("stock", |args| Err(Error::custom(OutOfStock { item: args[0] as u32 })));
+
+        let err = evaluate_with_defined("1 + stock(7)", None, Some(&funcs)).unwrap_err();
+        assert_eq!(err.to_string(), "item 7 is out of stock");
+        let Error::Custom(custom) = &err else { panic!("expected a custom error, got {:?}", err) };
+        assert_eq!(custom.downcast_ref::<OutOfStock>(), Some(&OutOfStock { item: 7 }));
+        assert_eq!(custom.downcast_ref::<core::fmt::Error>(), None);
+        assert!(std::error::Error::source(&err).is_some());
+
+        // clones share the error, so they are equal
+        assert_eq!(err.clone(), err);
+        assert_ne!(Error::custom(OutOfStock { item: 7 }), err);
+    }
+
+    #[test]
+    fn seeded_rng() {
+        let (a, b) = (Functions::with_rng(42), Functions::with_rng(42));
+        for input in ["rand()", "rand(-5, 5)", "randint(1, 6)", "randint(-1000000, 1000000)", "rand() + randint(0, 10)"] {
+            for _ in 0..20 {
+                assert_eq!(evaluate_with_defined(input, None, Some(&a)), evaluate_with_defined(input, None, Some(&b)));
+            }
+        }
+
+        let c = Functions::with_rng(7);
+        let sequence: Vec<f64> = (0..100).map(|_| evaluate_with_defined("rand()", None, Some(&c)).unwrap()).collect();
+        assert!(sequence.iter().all(|n| (0.0..1.0).contains(n)));
+        assert_ne!(sequence[0], sequence[1]);
+        for _ in 0..100 {
+            let n = evaluate_with_defined("randint(1, 3)", None, Some(&c)).unwrap();
+            assert!(n == 1.0 || n == 2.0 || n == 3.0);
+            let n = evaluate_with_defined("rand(2, 4)", None, Some(&c)).unwrap();
+            assert!((2.0..4.0).contains(&n));
+        }
+        assert_eq!(evaluate_with_defined("randint(5, 5)", None, Some(&c)), Ok(5.0));
+        assert!(matches!(evaluate_with_defined("randint(6, 1)", None, Some(&c)), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(evaluate_with_defined("randint(1.5, 3)", None, Some(&c)), Err(Error::InvalidArgument { .. })));
+        assert_eq!(evaluate_with_defined("rand(1)", None, Some(&c)), Err(Error::arg_count("rand", 2, 1)));
+        assert_eq!(evaluate_with_defined("sqrt(16)", None, Some(&c)), Ok(4.0));
+    }
+
+    #[test]
+    fn keywords_take_precedence() {
+        let mut funcs = Functions::new();
+        funcs.register("mod", |_| Ok(100.0));
+        funcs.register("div", |_| Ok(100.0));
+        funcs.register("modulo", |_| Ok(100.0));
+
+        assert_eq!(evaluate_with_defined("10 mod(3)", None, Some(&funcs)), Ok(1.0));
+        assert_eq!(evaluate_with_defined("10 div(3)", None, Some(&funcs)), Ok(3.0));
+        assert_eq!(evaluate_with_defined("mod(3)", None, Some(&funcs)), Err(Error::InvalidLeadingOperator { op: "%".to_string() }));
+        assert_eq!(evaluate_with_defined("modulo(3)", None, Some(&funcs)), Ok(100.0));
+    }
+
+    #[test]
+    fn comma_operator() {
+        let funcs = Functions::default();
+        assert_eq!(evaluate("(1+1, 2+2) == 4"), Ok(1.0));
+        assert_eq!(evaluate("(1, 2, 3)"), Ok(3.0));
+        assert_eq!(evaluate("2 * (1 < 2, 5 - 1) ^ 2"), Ok(32.0));
+        assert_eq!(evaluate("((1, 2), (3, 4 / 2))"), Ok(2.0));
+        assert_eq!(evaluate("(1, 2 / 0)"), Err(Error::DivByZero));
+        assert_eq!(evaluate_int("(7, 3) * 2"), Ok(6));
+        assert_eq!(evaluate_with_options("(1,5; 2,5)", None, None, &SolveOptions::builder().decimal_comma().build()), Ok(2.5));
+        assert_eq!(parse("(1 + 1, 2) * 3").unwrap().to_string(), "(1 + 1, 2) * 3");
+
+        // function arguments are still separated by commas
+        assert_eq!(evaluate_with_defined("max(1, 2) + (3, 4)", None, Some(&funcs)), Ok(6.0));
+
+        assert_eq!(evaluate("1, 2"), Err(Error::InvalidCharacter { c: ',' }));
+        assert_eq!(evaluate("|1, 2|"), Err(Error::InvalidCharacter { c: ',' }));
+        assert_eq!(evaluate("(1, )"), Err(Error::Expected { expected: "operand".to_string(), found: ")".to_string() }));
+        assert_eq!(evaluate("(1 +, 2)"), Err(Error::Expected { expected: "operand".to_string(), found: ",".to_string() }));
+    }
+
+    #[test]
+    fn diagnostics() {
+        assert_eq!(evaluate_checked("2 * (3 + 4)"), Ok(14.0));
+
+        let diagnostics = evaluate_checked("2 * 3 & 4").unwrap_err();
+        assert_eq!(diagnostics.error, Error::InvalidCharacter { c: '&' });
+        assert_eq!(diagnostics.stage, Stage::Lexing);
+        assert_eq!(diagnostics.position, 6);
+        assert_eq!(diagnostics.tokens, vec![
+            PublicToken { kind: TokenKind::Number, span: 0..1 },
+            PublicToken { kind: TokenKind::Operator(Operator::Mul), span: 2..3 },
+            PublicToken { kind: TokenKind::Number, span: 4..5 },
+        ]);
+        assert_eq!(diagnostics.to_string(), "Invalid character: & (while lexing, at byte 6 after 3 tokens)");
+
+        let diagnostics = evaluate_checked("(1 + 2").unwrap_err();
+        assert_eq!(diagnostics.error, Error::MismatchedParentheses { found: '(', missing: ')' });
+        assert_eq!(diagnostics.stage, Stage::Parsing);
+        assert_eq!((diagnostics.tokens.len(), diagnostics.position), (4, 6));
+
+        let diagnostics = evaluate_checked("1 / (2 - 2)").unwrap_err();
+        assert_eq!((diagnostics.error, diagnostics.stage), (Error::DivByZero, Stage::Evaluating));
+        assert_eq!(diagnostics.tokens.len(), 7);
+    }
+
+    #[test]
+    fn stray_assignment() {
+        let stray = Err(Error::InvalidOperator { op: "=".to_string() });
+        assert_eq!(evaluate("1 = 2"), stray);
+        assert_eq!(evaluate("2 + = 3"), stray);
+        assert_eq!(evaluate("(1 = 2) + 3"), stray);
+        assert_eq!(parse("1 = 2").unwrap_err(), Error::InvalidOperator { op: "=".to_string() });
+        assert_eq!(evaluate_with_defined("x = 1", Some(&Definitions::new()), None), stray);
+        assert_eq!(evaluate("= 3"), Err(Error::InvalidLeadingOperator { op: "=".to_string() }));
+        assert!(evaluate("1 = 2").unwrap_err().to_string().contains("`name = expression`"));
+
+        // assignments at the start of a statement are still allowed in programs
+        let mut defs = Definitions::new();
+        assert_eq!(evaluate_program("x = 2; y = x = 3", &mut defs, &Functions::new()), stray);
+        assert_eq!(evaluate_program("x = 2; x == 2", &mut defs, &Functions::new()), Ok(1.0));
+    }
+
+    #[test]
+    fn radix_formatting() {
+        assert_eq!(format_radix(255.0, 16), Ok("ff".to_string()));
+        assert_eq!(format_radix(255.0, 2), Ok("11111111".to_string()));
+        assert_eq!(format_radix(8.0, 8), Ok("10".to_string()));
+        assert_eq!(format_radix(0.0, 16), Ok("0".to_string()));
+        assert_eq!(format_radix(-0.0, 16), Ok("0".to_string()));
+        assert_eq!(format_radix(-4095.0, 16), Ok("-fff".to_string()));
+        assert_eq!(format_radix(35.0, 36), Ok("z".to_string()));
+        assert_eq!(format_radix(2f64.powi(100), 16), Ok(format!("1{}", "0".repeat(25))));
+        assert!(matches!(format_radix(2.5, 16), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(format_radix(f64::NAN, 16), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(format_radix(1e300, 16), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(format_radix(10.0, 1), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(format_radix(10.0, 37), Err(Error::InvalidArgument { .. })));
+    }
+
+    #[test]
+    fn function_signatures() {
+        let signatures = Functions::default().signatures();
+        assert!(signatures.contains(&("log".to_string(), "base, value".to_string())));
+        assert!(signatures.contains(&("max".to_string(), "values...".to_string())));
+        assert_eq!(signatures.len(), Functions::default().names().count());
+        assert!(signatures.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(signatures.iter().all(|(_, params)| params != "..."));
+
+        let mut calc = Calculator::new();
+        calc.functions_mut().register("custom", |_| Ok(0.0));
+        calc.define_function("area(w, h) = w * h").unwrap();
+        let signatures = calc.functions().signatures();
+        assert!(signatures.contains(&("custom".to_string(), "...".to_string())));
+        assert!(signatures.contains(&("area".to_string(), "w, h".to_string())));
+
+        assert!(calc.functions_mut().set_signature("custom", "x"));
+        assert!(calc.functions().signatures().contains(&("custom".to_string(), "x".to_string())));
+        assert!(!calc.functions_mut().set_signature("area", "x"));
+        assert!(!calc.functions_mut().set_signature("nope", "x"));
+        assert!(Functions::with_rng(0).signatures().contains(&("rand".to_string(), "[min, max]".to_string())));
+    }
+
+    #[test]
+    fn public_postfix() {
+        assert_eq!(to_postfix("2 + 3 * 4"), Ok(vec![
+            PostfixItem::Operand(Value::Int(2)),
+            PostfixItem::Operand(Value::Int(3)),
+            PostfixItem::Operand(Value::Int(4)),
+            PostfixItem::Operator(Operator::Mul),
+            PostfixItem::Operator(Operator::Add),
+        ]));
+        assert_eq!(to_postfix("-|x| ^ 2.5"), Ok(vec![
+            PostfixItem::Variable("x".to_string()),
+            PostfixItem::Operator(Operator::Abs),
+            PostfixItem::Operator(Operator::Neg),
+            PostfixItem::Operand(Value::Float(2.5)),
+            PostfixItem::Operator(Operator::Pow),
+        ]));
+        assert_eq!(to_postfix("f(g(1), -2)"), Ok(vec![
+            PostfixItem::Operand(Value::Int(1)),
+            PostfixItem::Function("g".to_string(), 1),
+            PostfixItem::Operand(Value::Int(-2)),
+            PostfixItem::Function("f".to_string(), 2),
+        ]));
+        assert_eq!(to_postfix("pi()"), Ok(vec![PostfixItem::Function("pi".to_string(), 0)]));
+        assert_eq!(to_postfix("(1 + 2"), Err(Error::MismatchedParentheses { found: '(', missing: ')' }));
+    }
+
+    #[test]
+    fn repeated_range_evaluation() {
+        let expr = Expression::compile("x * 2 + max(x, 5) % 7").unwrap();
+        let points = expr.eval_range("x", 0.0, 99_999.0, 1.0, None, Some(&Functions::default())).unwrap();
+        assert_eq!(points.len(), 100_000);
+        for (x, y) in points {
+            assert_eq!(y, x * 2.0 + x.max(5.0) % 7.0);
+        }
+    }
+
+    #[test]
+    fn repeated_evaluation_reuses_buffers() {
+        let options = SolveOptions::default();
+        let mut tokens = lex::lex(&mut InputReader::new("x * 2 + max(x, 5) % 7".to_string()), true, &options).unwrap();
+        let shunted = postfix::shunting_yard(&mut tokens, &options).unwrap();
+        let (jumps, funcs, mut defs) = (shunted.jumps(), Functions::default(), Definitions::new());
+        let mut scratch = interpret::Scratch::default();
+        defs.register("x", 1);
+        assert_eq!(interpret::interpret_reusing(&shunted, &jumps, interpret::Scope { definitions: Some(&defs), functions: Some(&funcs), depth: 0, resolver: None }, &options, &mut interpret::EvalState::default(), &mut scratch), Ok(7.0));
+        let buffers = (scratch.operand_stack.as_ptr(), scratch.calls.as_ptr());
+        for x in 2..100 {
+            defs.register("x", x);
+            let expected = x as f64 * 2.0 + (x as f64).max(5.0) % 7.0;
+            assert_eq!(interpret::interpret_reusing(&shunted, &jumps, interpret::Scope { definitions: Some(&defs), functions: Some(&funcs), depth: 0, resolver: None }, &options, &mut interpret::EvalState::default(), &mut scratch), Ok(expected));
+            assert_eq!((scratch.operand_stack.as_ptr(), scratch.calls.as_ptr()), buffers);
+        }
+    }
+
+    #[test]
+    fn signed_exponents() {
+        assert_eq!(evaluate("2 ^ +3"), Ok(8.0));
+        assert_eq!(evaluate("2^+3"), Ok(8.0));
+        assert_eq!(evaluate("2 ^ +(1 + 2)"), Ok(8.0));
+        assert_eq!(evaluate_int("2 ^ +3"), Ok(8));
+        // the sign makes the exponent negative before the power is applied
+        assert_eq!(evaluate("2 ^ -3"), Err(Error::NegativeExponent));
+        assert_eq!(evaluate("2 ^ -(1 + 2)"), Err(Error::NegativeExponent));
+        assert_eq!(evaluate("2^+-3"), Err(Error::NegativeExponent));
+        assert_eq!(evaluate_int("2 ^ -3"), Err(Error::NegativeExponent));
+        assert_eq!(evaluate("2 ^ --3"), Ok(8.0));
+    }
+
+    #[test]
+    fn boolean_results() {
+        assert_eq!(evaluate_bool("3 > 2", None, None), Ok(true));
+        assert_eq!(evaluate_bool("1 == 2", None, None), Ok(false));
+        assert_eq!(evaluate_bool("0.5 - 1", None, None), Ok(true));
+        assert_eq!(evaluate_bool("x > 1", None, None), Err(Error::InvalidCharacter { c: 'x' }));
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_eq!(evaluate("1 && 0"), Ok(0.0));
+        assert_eq!(evaluate("0 || 1"), Ok(1.0));
+        assert_eq!(evaluate("2 && -3"), Ok(1.0));
+        // comparisons are grouped before && and ||, and && before ||
+        assert_eq!(evaluate("1 && 0 == 0"), Ok(1.0));
+        assert_eq!(evaluate("0 || 1 == 1"), Ok(1.0));
+        assert_eq!(evaluate("1 || 1 && 0"), Ok(1.0));
+        assert_eq!(evaluate("!0"), Ok(1.0));
+        assert_eq!(evaluate("!(2 > 1) || !!3"), Ok(1.0));
+        assert_eq!(evaluate("-!0"), Ok(-1.0));
+        assert_eq!(evaluate_int("!7 || 5 && 6"), Ok(1));
+        assert_eq!(evaluate("5!"), Err(Error::InvalidOperator { op: "!".to_string() }));
+        assert_eq!(evaluate("1 & 1"), Err(Error::InvalidCharacter { c: '&' }));
+        // `||` after an operand is an or, anywhere else it opens two absolute value groups
+        assert_eq!(evaluate("|-2| || 0"), Ok(1.0));
+        assert_eq!(evaluate("||-2| - 3|"), Ok(1.0));
+
+        // the right side isn't evaluated when the left side decides the result
+        assert_eq!(evaluate("0 && 1 / 0"), Ok(0.0));
+        assert_eq!(evaluate("1 || 1 / 0"), Ok(1.0));
+        assert_eq!(evaluate("1 && 1 / 0"), Err(Error::DivByZero));
+        let defs = Definitions::new();
+        assert_eq!(evaluate_with_defined("0 && undefined_var", Some(&defs), None), Ok(0.0));
+        assert_eq!(evaluate_with_defined("1 && undefined_var", Some(&defs), None),
+            Err(Error::UndefinedVariable { name: "undefined_var".to_string() }));
+        assert_eq!(evaluate_with_defined("1 < 2 && 0 || undefined_var", Some(&defs), None),
+            Err(Error::UndefinedVariable { name: "undefined_var".to_string() }));
+        assert_eq!(evaluate_with_defined("undefined_var && 0", Some(&defs), None),
+            Err(Error::UndefinedVariable { name: "undefined_var".to_string() }));
+
+        // the result stays an integer, whether or not the right side is evaluated
+        assert_eq!(evaluate_value("9007199254740993 - 9007199254740992 + (1 && 1)"), Ok(Value::Int(2)));
+        assert_eq!(evaluate_value("1 < 2 && 2 < 3"), Ok(Value::Int(1)));
+        assert_eq!(evaluate_value("0 && 1 / 0"), Ok(Value::Int(0)));
+        assert_eq!(evaluate_value("2 || 1 / 0"), Ok(Value::Int(1)));
+        assert_eq!(parse("!a || b && c").unwrap().to_string(), "!a || b && c");
+    }
+
+    #[test]
+    fn si_suffixes() {
+        let options = SolveOptions::builder().si_suffixes().build();
+        let solve = |input: &str| evaluate_with_options(input, None, None, &options);
+        assert_eq!(solve("2k == 2000"), Ok(1.0));
+        assert_eq!(solve("500m == 0.5"), Ok(1.0));
+        assert_eq!(solve("3M + 1G + 2T"), Ok(2_001_003_000_000.0));
+        assert_eq!(solve("1.5k"), Ok(1500.0));
+        assert_eq!(solve("250u"), Ok(0.00025));
+        assert_eq!(evaluate_int_with_options("7G * 3", &options), Ok(21_000_000_000));
+        // without the option, the suffix isn't part of the number
+        assert_eq!(evaluate("2k"), Err(Error::InvalidCharacter { c: 'k' }));
+
+        // the suffix takes precedence over a variable with the same name, unless more letters follow it
+        let mut defs = Definitions::new();
+        defs.register("m", 4);
+        defs.register("km", 5);
+        assert_eq!(evaluate_with_options("2m", Some(&defs), None, &options), Ok(0.002));
+        assert_eq!(evaluate_with_options("2 m", Some(&defs), None, &options), Err(Error::AdjacentOperands { left: "2".to_string(), right: "m".to_string() }));
+        assert_eq!(evaluate_with_options("2km", Some(&defs), None, &options), Err(Error::AdjacentOperands { left: "2".to_string(), right: "km".to_string() }));
+        assert_eq!(evaluate_with_options("m * 1k", Some(&defs), None, &options), Ok(4000.0));
+    }
+
+    #[test]
+    fn binary_suffixes() {
+        let options = SolveOptions::builder().binary_suffixes().build();
+        let solve = |input: &str| evaluate_with_options(input, None, None, &options);
+        assert_eq!(solve("1Ki == 1024"), Ok(1.0));
+        assert_eq!(solve("1Mi == 1048576"), Ok(1.0));
+        assert_eq!(solve("1Gi"), Ok(1_073_741_824.0));
+        assert_eq!(solve("2Ti"), Ok(2_199_023_255_552.0));
+        assert_eq!(solve("0.5Ki"), Ok(512.0));
+        assert_eq!(evaluate_int_with_options("4Gi / 1Mi", &options), Ok(4096));
+        // a suffix needs a number before it, and more letters after it make it an identifier
+        assert_eq!(solve("Ki"), Err(Error::InvalidCharacter { c: 'K' }));
+        assert_eq!(evaluate_with_options("1Kib", Some(&Definitions::new()), None, &options),
+            Err(Error::AdjacentOperands { left: "1".to_string(), right: "Kib".to_string() }));
+        assert_eq!(evaluate("1Ki"), Err(Error::InvalidCharacter { c: 'K' }));
+
+        // both kinds of suffix can be used together
+        let options = SolveOptions::builder().si_suffixes().binary_suffixes().build();
+        assert_eq!(evaluate_with_options("1Mi - 1M", None, None, &options), Ok(48_576.0));
+    }
+
+    #[test]
+    fn variable_pairs() {
+        assert_eq!(evaluate_with_vars("a + b", &[("a", 2.0), ("b", 3.0)], None), Ok(5.0));
+        assert_eq!(evaluate_with_vars("x * 2", &[("x", 1.0), ("x", 4.0)], None), Ok(8.0));
+        assert_eq!(evaluate_with_vars("sqrt(a)", &[("a", 16.0)], Some(&Functions::default())), Ok(4.0));
+        assert_eq!(evaluate_with_vars("a + c", &[("a", 1.0)], None), Err(Error::UndefinedVariable { name: "c".to_string() }));
+        assert_eq!(evaluate_with_vars("1 + 1", &[], None), Ok(2.0));
+    }
+
+    #[test]
+    fn unterminated_calls() {
+        let funcs = Functions::default();
+        let unclosed = Err(Error::MismatchedParentheses { found: '(', missing: ')' });
+        assert_eq!(evaluate_with_defined("sqrt(4", None, Some(&funcs)), unclosed);
+        assert_eq!(evaluate_with_defined("sqrt(4 ", None, Some(&funcs)), unclosed);
+        assert_eq!(evaluate_with_defined("max(1, 2", None, Some(&funcs)), unclosed);
+        assert_eq!(evaluate_with_defined("max(1,", None, Some(&funcs)), unclosed);
+        assert_eq!(evaluate_with_defined("max(", None, Some(&funcs)), unclosed);
+        assert_eq!(evaluate_with_defined("f(g(2)", None, Some(&funcs)), unclosed);
+        assert_eq!(parse("f(g(2)").unwrap_err(), Error::MismatchedParentheses { found: '(', missing: ')' });
+    }
+
+    #[test]
+    fn spaces_in_numbers() {
+        assert_eq!(evaluate("1 000"), Err(Error::SpaceInNumber { number: "1 000".to_string() }));
+        assert_eq!(evaluate("1 000").unwrap_err().to_string(), "Numbers can't contain spaces, did you mean 1000?");
+        assert_eq!(evaluate("2 * 12 345 678.5"), Err(Error::SpaceInNumber { number: "12 345 678".to_string() }));
+        // other numbers next to each other aren't digit groups
+        assert_eq!(evaluate("1 2"), Err(Error::AdjacentOperands { left: "1".to_string(), right: "2".to_string() }));
+        assert_eq!(evaluate("1000 000"), Err(Error::AdjacentOperands { left: "1000".to_string(), right: "0".to_string() }));
+        assert_eq!(evaluate("1.5 000"), Err(Error::AdjacentOperands { left: "1.5".to_string(), right: "0".to_string() }));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn parallel_evaluation() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Expression>();
+
+        let expr = Expression::compile("a * x + 1").unwrap();
+        let handles: Vec<_> = (0..8).map(|x| {
+            let expr = expr.clone_for_eval();
+            std::thread::spawn(move || {
+                let mut defs = Definitions::new();
+                defs.register("a", 3);
+                defs.register("x", x);
+                expr.eval(Some(&defs), None)
+            })
+        }).collect();
+        for (x, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.join().unwrap(), Ok(3.0 * x as f64 + 1.0));
+        }
+    }
+
+    #[test]
+    fn warnings() {
+        assert_eq!(evaluate_with_warnings("9007199254740992 + 1"), Ok((9007199254740992.0, vec![Warning::PrecisionLoss])));
+        assert_eq!(evaluate_with_warnings("2 ^ 60 * 3"), Ok((3.0 * 2f64.powi(60), vec![])));
+        assert_eq!(evaluate_with_warnings("2 ^ 2000").map(|(_, warnings)| warnings), Ok(vec![Warning::Infinite]));
+        assert_eq!(evaluate_with_warnings("0 * 2 ^ 2000").map(|(_, warnings)| warnings), Ok(vec![Warning::Infinite, Warning::NotANumber]));
+        assert_eq!(evaluate_with_warnings("1 / 0"), Err(Error::DivByZero));
+        assert_eq!(Warning::PrecisionLoss.to_string(), "A value is too large to be exact and was rounded");
+
+        // values on the way to the result are checked, even if the result is exact
+        assert_eq!(evaluate_with_warnings("(2 ^ 60 + 1) * 1.0"), Ok((2f64.powi(60), vec![Warning::PrecisionLoss])));
+        assert_eq!(evaluate_with_warnings("(2 ^ 52 + 1) * 1.0"), Ok((2f64.powi(52) + 1.0, vec![])));
+        assert_eq!(evaluate_with_warnings("9007199254740993 == 9007199254740992.0").map(|(_, warnings)| warnings), Ok(vec![Warning::PrecisionLoss]));
+        assert_eq!(evaluate_with_warnings("1 / 2 ^ 2000"), Ok((0.0, vec![Warning::Infinite])));
+        assert_eq!(evaluate_with_warnings("2 ^ 2000 - 2 ^ 2000 > 0"), Ok((0.0, vec![Warning::Infinite, Warning::NotANumber])));
+        // each warning is only reported once
+        assert_eq!(evaluate_with_warnings("2 ^ 2000 + 2 ^ 3000").map(|(_, warnings)| warnings), Ok(vec![Warning::Infinite]));
+    }
+
+    #[test]
+    fn physical_constants() {
+        let defs = Definitions::with_physics();
+        assert_eq!(evaluate_with_defined("2 * c", Some(&defs), None), Ok(2.0 * 299_792_458.0));
+        assert_eq!(evaluate_with_defined("g", Some(&defs), None), Ok(9.80665));
+        assert_eq!(evaluate_with_defined("G", Some(&defs), None), Ok(6.67430e-11));
+        assert_eq!(evaluate_with_defined("Na * kB", Some(&defs), None), Ok(6.02214076e23 * 1.380649e-23));
+
+        // a variable with the same name replaces the constant
+        let mut defs = Definitions::with_physics();
+        defs.register("c", 3);
+        assert_eq!(evaluate_with_defined("c + h", Some(&defs), None), Ok(3.0 + 6.62607015e-34));
+    }
+
+    #[test]
+    fn operator_lookahead() {
+        let mut input = InputReader::new(">=");
+        assert_eq!(input.peek_at(1), Some('='));
+        assert_eq!(input.peek_at(2), None);
+        assert_eq!(input.peek_at(usize::MAX), None);
+        input.consume();
+        assert_eq!(input.peek_at(1), None);
+        input.consume();
+        assert_eq!(input.peek(), None);
+        assert_eq!(input.consume(), None);
+
+        assert_eq!(evaluate("2 >= 2"), Ok(1.0));
+        assert_eq!(evaluate("2>=3"), Ok(0.0));
+        assert_eq!(evaluate("2 ** 3"), Ok(8.0));
+        assert_eq!(tokenize("1>=2").unwrap()[1], PublicToken { kind: TokenKind::Operator(Operator::GreaterEq), span: 1..3 });
+        // the first character of an operator at the end of the input isn't read past
+        assert_eq!(evaluate("2 >"), Err(Error::UnexpectedEOI));
+        assert_eq!(evaluate("2 <"), Err(Error::UnexpectedEOI));
+        assert_eq!(evaluate("2 *"), Err(Error::UnexpectedEOI));
+        assert_eq!(evaluate("2 /"), Err(Error::UnexpectedEOI));
+        assert_eq!(evaluate("1 &"), Err(Error::InvalidCharacter { c: '&' }));
+        assert_eq!(evaluate("1 !"), Err(Error::InvalidOperator { op: "!".to_string() }));
+    }
+
+    #[test]
+    fn argument_resolution() {
+        let funcs = Functions::default();
+        let mut defs = Definitions::new();
+        defs.register("x", 16);
+
+        // variables come from the definitions, and calls are evaluated
+        assert_eq!(evaluate_with_defined("sqrt(x)", Some(&defs), Some(&funcs)), Ok(4.0));
+        assert_eq!(evaluate_with_defined("max(x, sqrt(4))", Some(&defs), Some(&funcs)), Ok(16.0));
+        assert_eq!(evaluate_with_defined("max(1, sqrt(4))", None, Some(&funcs)), Ok(2.0));
+        // errors in a nested call are returned as they are
+        assert_eq!(evaluate_with_defined("max(1, sqrt(1, 2))", None, Some(&funcs)), Err(Error::arg_count("sqrt", 1, 2)));
+
+        // a function's name isn't a value, with or without definitions
+        let not_a_value = Err(Error::NotAValue { name: "sqrt".to_string() });
+        assert_eq!(evaluate_with_defined("max(1, sqrt)", Some(&defs), Some(&funcs)), not_a_value);
+        assert_eq!(evaluate_with_defined("max(1, sqrt)", None, Some(&funcs)), not_a_value);
+
+        // an undefined variable is only reported as one if there are variables it could have been
+        assert_eq!(evaluate_with_defined("sqrt(y)", Some(&defs), Some(&funcs)), Err(Error::UndefinedVariable { name: "y".to_string() }));
+        assert_eq!(evaluate_with_defined("sqrt(y)", None, Some(&funcs)), Err(Error::InvalidArgument { name: "sqrt".to_string(), value: "y".to_string() }));
+        let strict = SolveOptions::builder().strict_identifiers().build();
+        assert_eq!(evaluate_with_options("sqrt(y)", None, Some(&funcs), &strict), Err(Error::UndefinedVariable { name: "y".to_string() }));
+    }
+
+    #[test]
+    fn variable_juxtaposition() {
+        let options = SolveOptions::builder().implicit_mul_unknown_calls().build();
+        let funcs = Functions::default();
+        let mut defs = Definitions::new();
+        defs.register("x", 5);
+        let solve = |input: &str| evaluate_with_options(input, Some(&defs), Some(&funcs), &options);
+        assert_eq!(solve("x(2+3) == 25"), Ok(1.0));
+        assert_eq!(solve("x(2)"), Ok(10.0));
+        assert_eq!(solve("-x(x - 1) + 1"), Ok(-19.0));
+        assert_eq!(solve("sqrt(x(5))"), Err(Error::NotCallable { name: "x".to_string() }));
+        // functions are still called, and the '(' has to be right after the name
+        assert_eq!(solve("sqrt(4)"), Ok(2.0));
+        assert_eq!(solve("x (2)"), Err(Error::MissingOperator));
+        // a variable with the same name as a function is called
+        defs.register("sqrt", 3);
+        assert_eq!(evaluate_with_options("sqrt(16)", Some(&defs), Some(&funcs), &options), Ok(4.0));
+
+        // without the option, variables can't be called
+        assert_eq!(evaluate_with_defined("x(2)", Some(&defs), None), Err(Error::NotCallable { name: "x".to_string() }));
+    }
+
+    #[test]
+    fn significant_figures() {
+        assert_eq!(Value::Int(123456).format_sig_figs(3), "123000");
+        assert_eq!(Value::Int(123556).format_sig_figs(3), "124000");
+        assert_eq!(Value::Int(-99999).format_sig_figs(2), "-100000");
+        assert_eq!(Value::Int(42).format_sig_figs(5), "42");
+        assert_eq!(Value::Int(i128::MAX).format_sig_figs(2), "170000000000000000000000000000000000000");
+        assert_eq!(Value::Float(0.0012345).format_sig_figs(2), "0.0012");
+        assert_eq!(Value::Float(-0.0012355).format_sig_figs(3), "-0.00124");
+        assert_eq!(Value::Float(6.02214076e23).format_sig_figs(4), "602200000000000000000000");
+        assert_eq!(Value::Float(1.6e-10).format_sig_figs(1), "0.0000000002");
+        assert_eq!(Value::Float(1.23456).format_sig_figs(3), "1.23");
+        assert_eq!(Value::Float(9.99).format_sig_figs(2), "10");
+        assert_eq!(Value::Float(2.5).format_sig_figs(3), "2.50");
+        // exact powers of ten
+        assert_eq!(Value::Float(1000.0).format_sig_figs(2), "1000");
+        assert_eq!(Value::Float(0.001).format_sig_figs(1), "0.001");
+        assert_eq!(Value::Float(10.0).format_sig_figs(3), "10.0");
+        assert_eq!(Value::Int(1000).format_sig_figs(1), "1000");
+        assert_eq!(Value::Float(0.0).format_sig_figs(3), "0.00");
+        assert_eq!(Value::Float(f64::INFINITY).format_sig_figs(3), "inf");
+    }
+
+    #[test]
+    fn incremental_parsing() {
+        assert_eq!(try_parse("1 + 2"), ParseStatus::Complete);
+        assert_eq!(try_parse("sqrt(x) * |y|"), ParseStatus::Complete);
+        assert_eq!(try_parse("1 +"), ParseStatus::Incomplete);
+        assert_eq!(try_parse("(1 + 2"), ParseStatus::Incomplete);
+        assert_eq!(try_parse("|1 - 2"), ParseStatus::Incomplete);
+        assert_eq!(try_parse("max(1,"), ParseStatus::Incomplete);
+        assert_eq!(try_parse("-"), ParseStatus::Incomplete);
+        assert_eq!(try_parse("  "), ParseStatus::Incomplete);
+        assert_eq!(try_parse("1 + @"), ParseStatus::Error(Error::InvalidCharacter { c: '@' }));
+        assert_eq!(try_parse("1 + 2)"), ParseStatus::Error(Error::MismatchedParentheses { found: ')', missing: '(' }));
+        assert_eq!(try_parse("(|2)|"), ParseStatus::Error(Error::MismatchedParentheses { found: ')', missing: '|' }));
+        assert_eq!(try_parse("(1|"), ParseStatus::Error(Error::MismatchedParentheses { found: '|', missing: ')' }));
+        assert_eq!(try_parse("2 3"), ParseStatus::Error(Error::AdjacentOperands { left: "2".to_string(), right: "3".to_string() }));
+        assert_eq!(try_parse("(2) 3"), ParseStatus::Error(Error::AdjacentOperands { left: "(...)".to_string(), right: "3".to_string() }));
+        assert_eq!(try_parse("(1)(2)"), ParseStatus::Error(Error::AdjacentOperands { left: "(...)".to_string(), right: "(...)".to_string() }));
+    }
+
+    #[test]
+    fn special_floats() {
+        let options = SolveOptions::builder().allow_special_floats().build();
+        let solve = |input: &str| evaluate_with_options(input, None, None, &options);
+        assert_eq!(solve("inf + 1"), Ok(f64::INFINITY));
+        assert_eq!(solve("-inf"), Ok(f64::NEG_INFINITY));
+        assert_eq!(solve("inf == inf"), Ok(1.0));
+        assert_eq!(solve("nan == nan"), Ok(0.0));
+        assert_eq!(solve("nan != nan"), Ok(1.0));
+        assert!(solve("inf - inf").unwrap().is_nan());
+        assert!(solve("nan * 0").unwrap().is_nan());
+        assert_eq!(solve("1 / inf"), Ok(0.0));
+        assert_eq!(solve("2 inf"), Err(Error::AdjacentOperands { left: "2".to_string(), right: "inf".to_string() }));
+
+        // longer names and function calls aren't special
+        let mut defs = Definitions::new();
+        defs.register("info", 2);
+        defs.register("inf", 3);
+        assert_eq!(evaluate_with_options("info + inf", Some(&defs), None, &options), Ok(f64::INFINITY));
+        let mut funcs = Functions::new();
+        funcs.register("nan", |_| Ok(4.0));
+        assert_eq!(evaluate_with_options("nan()", None, Some(&funcs), &options), Ok(4.0));
+
+        // off by default
+        assert_eq!(evaluate("inf + 1"), Err(Error::InvalidCharacter { c: 'i' }));
+        assert_eq!(evaluate_with_defined("inf", Some(&Definitions::new()), None), Err(Error::UndefinedVariable { name: "inf".to_string() }));
+    }
+
+    #[test]
+    fn sampled_equivalence() {
+        let samples: Vec<Definitions> = [(1.0, 2.0, 3.0), (0.1, 0.2, 0.3), (-4.5, 1e10, -7.25), (0.0, 0.0, 0.0)].iter().map(|&(a, x, y)| {
+            let mut defs = Definitions::new();
+            defs.register("a", a);
+            defs.register("x", x);
+            defs.register("y", y);
+            defs
+        }).collect();
+        let expanded = Expression::compile("a*x + a*y").unwrap();
+        let factored = Expression::compile("a*(x+y)").unwrap();
+        assert_eq!(expanded.probably_equivalent(&factored, &samples), Ok(true));
+        assert_eq!(factored.probably_equivalent(&expanded, &samples), Ok(true));
+        assert_eq!(expanded.probably_equivalent(&Expression::compile("a*x + y").unwrap(), &samples), Ok(false));
+        // the first and last samples agree, so only the others tell these apart
+        assert_eq!(expanded.probably_equivalent(&Expression::compile("a*x + a*y*a").unwrap(), &samples[..1]), Ok(true));
+        assert_eq!(expanded.probably_equivalent(&Expression::compile("a*x + a*y*a").unwrap(), &samples), Ok(false));
+        assert_eq!(expanded.probably_equivalent(&Expression::compile("a / x").unwrap(), &samples[3..]), Err(Error::DivByZero));
+        assert_eq!(expanded.probably_equivalent(&Expression::compile("b").unwrap(), &[]), Ok(true));
+
+        // an infinite result is only the same as the same infinity, however large the other one is
+        let mut defs = Definitions::new();
+        defs.register("x", 2);
+        let samples = [defs];
+        let huge = Expression::compile("x ^ 1000").unwrap();
+        assert_eq!(Expression::compile("x ^ 2000").unwrap().probably_equivalent(&huge, &samples), Ok(false));
+        assert_eq!(Expression::compile("x ^ 2000").unwrap().probably_equivalent(&Expression::compile("x ^ 3000 + 1").unwrap(), &samples), Ok(true));
+        assert_eq!(Expression::compile("x ^ 2000").unwrap().probably_equivalent(&Expression::compile("-(x ^ 2000)").unwrap(), &samples), Ok(false));
+        assert_eq!(Expression::compile("x ^ 2000 - x ^ 2000").unwrap().probably_equivalent(&Expression::compile("x ^ 3000 * 0").unwrap(), &samples), Ok(true));
+    }
+
+    #[test]
+    fn radix_literals() {
+        assert_eq!(evaluate("0xff + 1"), Ok(256.0));
+        assert_eq!(evaluate("0XFF"), Ok(255.0));
+        assert_eq!(evaluate("0b1010 * 0o17"), Ok(150.0));
+        assert_eq!(evaluate_int("-0x7fffffffffffffffffffffffffffffff"), Ok(-i128::MAX));
+        // the whole literal is reported when it isn't valid
+        assert_eq!(evaluate("0xG"), Err(Error::InvalidNumber { found: "0xG".to_string() }));
+        assert_eq!(evaluate("0b2"), Err(Error::InvalidNumber { found: "0b2".to_string() }));
+        assert_eq!(evaluate("0o9"), Err(Error::InvalidNumber { found: "0o9".to_string() }));
+        assert_eq!(evaluate("1 + 0x"), Err(Error::InvalidNumber { found: "0x".to_string() }));
+        assert_eq!(evaluate("0x1fz + 1"), Err(Error::InvalidNumber { found: "0x1fz".to_string() }));
+        assert_eq!(evaluate_with_defined("0xG", Some(&Definitions::new()), None), Err(Error::InvalidNumber { found: "0xG".to_string() }));
+        assert_eq!(evaluate("0x100000000000000000000000000000000"), Err(Error::InvalidNumber { found: "0x100000000000000000000000000000000".to_string() }));
+    }
+
+    #[test]
+    fn reserved_names() {
+        let mut defs = Definitions::new();
+        assert_eq!(defs.register_checked("sin", 1.0), Err(Error::Other("'sin' is a reserved name and can't be used as a variable".to_string())));
+        assert!(!defs.exists("sin"));
+        assert_eq!(defs.register_checked("x", 1.0), Ok(()));
+        assert_eq!(defs.register_checked("inf", 3), Err(Error::Other("'inf' is a reserved name and can't be used as a variable".to_string())));
+        assert_eq!(defs.register_checked("pi", 3), Ok(()));
+        assert!(defs.register_checked("mod", 3).is_err());
+        assert!(defs.register_checked("sine", 3).is_ok());
+        // the lenient version still allows them
+        defs.register("sin", 2);
+        assert_eq!(evaluate_with_defined("sin + x", Some(&defs), None), Ok(3.0));
+
+        // every default function is reserved, and everything else that is reserved is a keyword or literal
+        let funcs = Functions::with_rng(0);
+        for name in funcs.names() {
+            assert!(Definitions::RESERVED.contains(&name.as_str()), "{} isn't reserved", name);
+        }
+        for name in Definitions::RESERVED {
+            assert!(funcs.exists(*name) || ["mod", "div", "inf", "nan"].contains(name), "{} isn't a function", name);
+        }
+    }
+
+    #[test]
+    fn chained_comparisons() {
+        assert_eq!(evaluate("1 < 2 < 3"), Ok(1.0));
+        assert_eq!(evaluate("3 < 2 < 1"), Ok(0.0));
+        assert_eq!(evaluate("1 <= 1 < 2"), Ok(1.0));
+        assert_eq!(evaluate("1 < 3 > 2"), Ok(1.0));
+        assert_eq!(evaluate("1 < 2 < 3 < 2"), Ok(0.0));
+        assert_eq!(evaluate("2 == 2 == 2"), Ok(1.0));
+        assert_eq!(evaluate("0 < 2 * 3 - 1 <= 5 && 1"), Ok(1.0));
+        // a parenthesized comparison isn't part of a chain, so its result is compared
+        assert_eq!(evaluate("(3 < 2) < 1"), Ok(1.0));
+
+        let mut defs = Definitions::new();
+        defs.register("x", 5);
+        assert_eq!(evaluate_with_defined("1 < x < 10", Some(&defs), None), Ok(1.0));
+        assert_eq!(evaluate_with_defined("1 < x * 2 < 10", Some(&defs), None), Ok(0.0));
+        assert_eq!(evaluate_with_defined("1 < |-x| <= 5", Some(&defs), None), Ok(1.0));
+        assert_eq!(evaluate_int("1 < 2 < 3"), Ok(1));
+        assert_eq!(parse("a < b + 1 < c").unwrap().to_string(), "a < b + 1 && b + 1 < c");
+        assert_eq!(Expression::compile("1 < x < 10").unwrap().debug_postfix(), "1 x < x 10 < &&");
+
+        // the operand between two comparisons is only evaluated once
+        let counter = core::cell::Cell::new(0);
+        let mut funcs = Functions::default();
+        funcs.register("next", |_| {
+            counter.set(counter.get() + 1);
+            Ok(counter.get() as f64)
+        });
+        assert_eq!(evaluate_with_defined("0 < next() < 1.5", None, Some(&funcs)), Ok(1.0));
+        assert_eq!(counter.get(), 1);
+        assert_eq!(evaluate_with_defined("0 < next() * 2 < 5 < next() + 5 <= 10", None, Some(&funcs)), Ok(1.0));
+        assert_eq!(counter.get(), 3);
+        // or not at all after a comparison that is false
+        assert_eq!(evaluate_with_defined("5 < 1 < next()", None, Some(&funcs)), Ok(0.0));
+        assert_eq!(counter.get(), 3);
+        let options = SolveOptions::builder().fold_constants().build();
+        let expr = Expression::compile_with_options("1 + 1 < next() < 6 - 1", &options).unwrap();
+        assert_eq!(expr.eval(None, Some(&funcs)), Ok(1.0));
+        assert_eq!(counter.get(), 4);
+        assert_eq!(evaluate_value("1 < 2 < 3"), Ok(Value::Int(1)));
+        // so a random number is compared to both bounds
+        for seed in 0..20 {
+            let in_range = evaluate_with_defined("0 <= rand() < 0.5", None, Some(&Functions::with_rng(seed)));
+            assert_eq!(in_range, evaluate_with_defined("rand() < 0.5", None, Some(&Functions::with_rng(seed))));
+        }
+    }
+
+    #[test]
+    fn format_expressions() {
+        assert_eq!(format_expr("1+2 *3"), Ok("1 + 2 * 3".to_string()));
+        assert_eq!(format_expr("(1 + 2) * 3"), Ok("(1 + 2) * 3".to_string()));
+        assert_eq!(format_expr("(8 - 3) - 2"), Ok("8 - 3 - 2".to_string()));
+        assert_eq!(format_expr("8 - (3 - 2)"), Ok("8 - (3 - 2)".to_string()));
+        assert_eq!(format_expr("2 ^ (3 ^ 2)"), Ok("2 ^ 3 ^ 2".to_string()));
+        assert_eq!(format_expr("(2 ^ 3) ^ 2"), Ok("(2 ^ 3) ^ 2".to_string()));
+        assert_eq!(format_expr("-(x+1)*log(2,y)"), Ok("-(x + 1) * log(2, y)".to_string()));
+        assert_eq!(format_expr("-(-(3))"), Ok("-(-3)".to_string()));
+        assert_eq!(evaluate("-(-3)"), Ok(3.0));
+        assert_eq!(format_expr("(5 > 4) > 3"), Ok("(5 > 4) > 3".to_string()));
+        assert_eq!(format_expr("(1 < 2) == (3 < 4)"), Ok("(1 < 2) == (3 < 4)".to_string()));
+        for input in ["(5 > 4) > 3", "(1 < 2) == (3 < 4)"] {
+            assert_eq!(evaluate(format_expr(input).unwrap().as_str()), evaluate(input));
+        }
+    }
+
+    #[test]
+    fn format_is_idempotent() {
+        for input in ["1+2 *3", "(1 + 2) * (3 - x) / 4", "8-(3-2)", "-(-(3))", "2^(1+1)^3", "f(x, 2)*-y", "(5 > 4) > 3", "(1 < 2) == (3 < 4)"] {
+            let once = format_expr(input).unwrap();
+            let twice = format_expr(once.as_str()).unwrap();
+            assert_eq!(once, twice);
+        }
+    }
+}
+
+/// Tests the `no_std` implementations (`libm` math and `hashbrown` maps).
+/// Run with `cargo test --no-default-features --features libm`.
+#[cfg(all(test, not(feature = "std")))]
+mod no_std_test {
+    use super::*;
+
+    #[test]
+    fn libm_functions() {
+        let funcs = Functions::default();
+        assert_eq!(evaluate_with_defined("sqrt(16)", None, Some(&funcs)), Ok(4.0));
+        assert_eq!(evaluate_with_defined("log(2, 8)", None, Some(&funcs)), Ok(3.0));
+        assert_eq!(evaluate_with_defined("sin(0) + cos(0)", None, Some(&funcs)), Ok(1.0));
+        assert_eq!(evaluate("2 ^ 10"), Ok(1024.0));
+
+        let euclidean = SolveOptions { modulo_mode: ModuloMode::Euclidean, ..Default::default() };
+        assert_eq!(evaluate_with_options("(0 - 7) % 3", None, None, &euclidean), Ok(2.0));
+    }
+
+    #[test]
+    fn hashbrown_definitions() {
+        let mut defs = Definitions::new();
+        defs.register("x", 2);
+        assert!(defs.exists("x"));
+        assert_eq!(evaluate_with_defined("x * 3", Some(&defs), None), Ok(6.0));
+    }
+}
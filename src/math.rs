@@ -0,0 +1,43 @@
+//! Floating point functions that work with and without the standard library.
+//! `f64` only has these methods when `std` is available, so `no_std` builds use `libm` instead.
+
+/// Defines a function taking `f64` arguments that calls the `f64` method of the same name
+/// with `std`, or the given `libm` function without it.
+macro_rules! float_fn {
+    ($name:ident, $libm:ident, $x:ident $(, $arg:ident)*) => {
+        pub(crate) fn $name($x: f64 $(, $arg: f64)*) -> f64 {
+            #[cfg(feature = "std")]
+            return $x.$name($($arg),*);
+            #[cfg(not(feature = "std"))]
+            return libm::$libm($x $(, $arg)*);
+        }
+    };
+}
+
+float_fn!(sqrt, sqrt, x);
+float_fn!(sin, sin, x);
+float_fn!(cos, cos, x);
+float_fn!(tan, tan, x);
+float_fn!(asin, asin, x);
+float_fn!(acos, acos, x);
+float_fn!(atan, atan, x);
+float_fn!(atan2, atan2, y, x);
+float_fn!(sinh, sinh, x);
+float_fn!(cosh, cosh, x);
+float_fn!(tanh, tanh, x);
+float_fn!(ln, log, x);
+float_fn!(powf, pow, x, n);
+float_fn!(trunc, trunc, x);
+float_fn!(round, round, x);
+float_fn!(cbrt, cbrt, x);
+
+/// The logarithm of `x` in the given base
+pub(crate) fn log(x: f64, base: f64) -> f64 {
+    ln(x) / ln(base)
+}
+
+/// The remainder of `x / rhs`, which is always non-negative
+pub(crate) fn rem_euclid(x: f64, rhs: f64) -> f64 {
+    let r = x % rhs;
+    if r < 0.0 { r + rhs.abs() } else { r }
+}
@@ -1,73 +1,346 @@
-use std::fmt::{Display, Formatter};
-use crate::Error;
+use alloc::string::ToString;
+use core::fmt::{Display, Formatter};
+use crate::{DivByZeroMode, Error, ModuloMode, OverflowMode, SolveOptions};
+use crate::math;
+use crate::value::Value;
 
+/// The side from which a chain of operators with the same precedence is grouped.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Associativity {
+    /// `a - b - c` is evaluated as `(a - b) - c`
+    Left,
+    /// `a ^ b ^ c` is evaluated as `a ^ (b ^ c)`
+    Right,
+    /// The operator isn't grouped with others by associativity, i.e. `,`, which is grouped by the parentheses around it
+    None,
+}
+
+/// An operator that can appear in an expression.
+///
+/// More operators may be added in the future, so matching on one needs a wildcard arm.
+#[non_exhaustive]
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub(crate) enum Operator {
-    LeftParen,  // (
-    RightParen, // )
-    Add,        // +
-    Sub,        // -
-    Mul,        // *
-    Div,        // / or ÷
-    Mod,        // %
-    Assign,     // =
-    Pow,        // ^
+pub enum Operator {
+    /// Addition, written `+`
+    Add,
+    /// Subtraction, written `-` or `−`
+    Sub,
+    /// Multiplication, written `*`, `×` or `·`
+    Mul,
+    /// Division, written `/` or `÷`
+    Div,
+    /// The remainder of a division, written `%` or `mod`. See `SolveOptions::modulo_mode`
+    Mod,
+    /// Division that truncates toward zero, written `//` or `div`
+    IntDiv,
+    /// Exponentiation, written `^` or `**`
+    Pow,
+    /// Unary negation, written `-` before an operand
+    Neg,
+    /// A percentage, written `%` after an operand with `SolveOptions::percent_mode`
+    Percent,
+    /// The absolute value of an operand, written by surrounding it with `|`
+    Abs,
+    /// Equality, written `==`
+    Eq,
+    /// Inequality, written `!=` or `≠`
+    NotEq,
+    /// Less than, written `<`
+    Less,
+    /// Greater than, written `>`
+    Greater,
+    /// Less than or equal to, written `<=` or `≤`
+    LessEq,
+    /// Greater than or equal to, written `>=` or `≥`
+    GreaterEq,
+    /// The comma operator inside parentheses, which results in its right operand.
+    /// Written `,`, or `;` with `SolveOptions::decimal_comma`
+    Comma,
+    /// Logical and, written `&&`
+    And,
+    /// Logical or, written `||`
+    Or,
+    /// Logical not, written `!` before an operand
+    Not,
 }
 
 impl Operator {
-    /// returns a number from 0 to 2 depending on its precedence, with 3 being the highest
-    /// if the operator does not have a precedence, returns None
+    /// returns a number from 0 to 6 depending on its precedence, with 6 being the highest:
+    /// - 0: `||`
+    /// - 1: `&&`
+    /// - 2: comparisons (`==`, `!=`, `<`, `>`, `<=` and `>=`)
+    /// - 3: `+` and `-`
+    /// - 4: `*`, `/`, `//` and `%`
+    /// - 5: `^`
+    /// - 6: unary `-`, `!`, postfix `%` and absolute value
+    ///
+    /// if the operator does not have a precedence, returns None. This includes `,`, which is lower than
+    /// every other operator and only grouped by the parentheses around it.
     pub fn precedence(&self) -> Option<u8> {
         match self {
-            Operator::Add | Operator::Sub => Some(0),
-            Operator::Mul | Operator::Div | Operator::Mod => Some(1),
-            Operator::Pow => Some(2),
+            Operator::Or => Some(0),
+            Operator::And => Some(1),
+            _ if self.is_comparison() => Some(2),
+            Operator::Add | Operator::Sub => Some(3),
+            Operator::Mul | Operator::Div | Operator::IntDiv | Operator::Mod => Some(4),
+            Operator::Pow => Some(5),
+            Operator::Neg | Operator::Not | Operator::Percent | Operator::Abs => Some(6),
             _ => None,
         }
     }
 
-    pub(crate) fn can_apply(&self) -> bool {
+    /// returns true if the operator compares its operands, resulting in 1 if the comparison is true and 0 if not
+    pub(crate) fn is_comparison(&self) -> bool {
+        matches!(self, Operator::Eq | Operator::NotEq | Operator::Less | Operator::Greater | Operator::LessEq | Operator::GreaterEq)
+    }
+
+    /// compares the operands, treating values within `options.float_epsilon` of each other as equal,
+    /// so `0.1 + 0.2` is neither less nor greater than `0.3`. Infinite and NaN values are compared exactly
+    fn compare(&self, left: f64, right: f64, options: &SolveOptions) -> bool {
+        let equal = if left.is_finite() && right.is_finite() {
+            let scale = left.abs().max(right.abs()).max(1.0);
+            (left - right).abs() <= options.float_epsilon * scale
+        } else {
+            left == right
+        };
+        match self {
+            Operator::Eq => equal,
+            Operator::NotEq => !equal,
+            Operator::Less => left < right && !equal,
+            Operator::Greater => left > right && !equal,
+            Operator::LessEq => left < right || equal,
+            Operator::GreaterEq => left > right || equal,
+            _ => panic!("Operator::compare() called on non-comparison operator"),
+        }
+    }
+
+    /// returns how chains of this operator are grouped, i.e. `2 ^ 3 ^ 2 = 2 ^ (3 ^ 2)`
+    pub fn associativity(&self) -> Associativity {
         match self {
-            Operator::LeftParen | Operator::RightParen | Operator::Assign => false,
-            _ => true,
+            Operator::Pow | Operator::Neg | Operator::Not => Associativity::Right,
+            Operator::Comma => Associativity::None,
+            _ => Associativity::Left,
         }
     }
 
-    pub(crate) fn apply(&self, left: f64, right: f64) -> Result<f64, Error> {
+    /// returns true if chains of this operator are grouped from the right
+    pub(crate) fn is_right_associative(&self) -> bool {
+        self.associativity() == Associativity::Right
+    }
+
+    /// returns true if the operator only takes a single operand
+    pub(crate) fn is_unary(&self) -> bool {
+        matches!(self, Operator::Neg | Operator::Not | Operator::Percent | Operator::Abs)
+    }
+
+    pub(crate) fn apply_unary(&self, operand: f64) -> Result<f64, Error> {
+        Ok(match self {
+            Operator::Neg => -operand,
+            Operator::Percent => operand / 100.0,
+            Operator::Abs => operand.abs(),
+            Operator::Not => if operand == 0.0 { 1.0 } else { 0.0 },
+            _ => panic!("Operator::apply_unary() called on non-unary operator"),
+        })
+    }
+
+    pub(crate) fn apply(&self, left: f64, right: f64, options: &SolveOptions) -> Result<f64, Error> {
         Ok(match self {
             Operator::Add => left + right,
             Operator::Sub => left - right,
             Operator::Mul => left * right,
-            Operator::Div => {
-                if right == 0.0 {
-                    return Err(Error::DivByZero);
-                }
-                left / right
+            Operator::Div | Operator::IntDiv | Operator::Mod if right == 0.0 => match options.div_by_zero {
+                DivByZeroMode::Error => return Err(Error::DivByZero),
+                DivByZeroMode::NaN => f64::NAN,
+                DivByZeroMode::Infinity => if *self == Operator::Mod { f64::NAN } else { left / right },
+            },
+            Operator::Div => left / right,
+            Operator::IntDiv => math::trunc(left / right),
+            Operator::Mod => match options.modulo_mode {
+                ModuloMode::Truncated => left % right,
+                ModuloMode::Euclidean => math::rem_euclid(left, right),
             },
-            Operator::Mod => left % right,
             Operator::Pow => {
                 if right < 0.0 {
                     return Err(Error::NegativeExponent);
                 }
-                left.powf(right)
+                math::powf(left, right)
             }
-            _ => panic!("Operator::apply() called on non-operator"),
+            _ if self.is_comparison() => if self.compare(left, right, options) { 1.0 } else { 0.0 },
+            Operator::Comma => right,
+            Operator::And => if left != 0.0 && right != 0.0 { 1.0 } else { 0.0 },
+            Operator::Or => if left != 0.0 || right != 0.0 { 1.0 } else { 0.0 },
+            _ => panic!("Operator::apply() called on unary operator"),
         })
     }
+
+    /// applies a unary operator, keeping integers exact unless the result is too large to be an integer
+    pub(crate) fn apply_unary_value(&self, operand: Value) -> Result<Value, Error> {
+        if let Value::Int(i) = operand {
+            if *self != Operator::Percent {
+                if let Ok(result) = self.apply_unary_int_in_mode(i, OverflowMode::Checked) {
+                    return Ok(Value::Int(result));
+                }
+            }
+        }
+        Ok(Value::Float(self.apply_unary(operand.as_f64())?))
+    }
+
+    /// applies the operator, using exact integer arithmetic when both operands are integers.
+    /// The result is only a float when an operand is, the result is too large to be an integer,
+    /// or an integer division has a remainder (i.e. `7 / 2 = 3.5`)
+    pub(crate) fn apply_value(&self, left: Value, right: Value, options: &SolveOptions) -> Result<Value, Error> {
+        if let (Value::Int(l), Value::Int(r)) = (left, right) {
+            // division and modulo by zero are left to the float path, where `x % 0` is NaN
+            let exact = match self {
+                Operator::Div => r != 0 && l.checked_rem(r) == Some(0),
+                Operator::IntDiv | Operator::Mod => r != 0,
+                _ => true,
+            };
+            if exact {
+                match self.apply_int_in_mode(l, r, options, OverflowMode::Checked) {
+                    Ok(result) => return Ok(Value::Int(result)),
+                    Err(Error::Overflow) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(Value::Float(self.apply(left.as_f64(), right.as_f64(), options)?))
+    }
+
+    /// applies a unary operator with integer arithmetic, handling overflow according to `options.overflow_mode`
+    pub(crate) fn apply_unary_int(&self, operand: i128, options: &SolveOptions) -> Result<i128, Error> {
+        self.apply_unary_int_in_mode(operand, options.overflow_mode)
+    }
+
+    fn apply_unary_int_in_mode(&self, operand: i128, mode: OverflowMode) -> Result<i128, Error> {
+        let result = match self {
+            Operator::Neg => match mode {
+                OverflowMode::Checked => operand.checked_neg(),
+                OverflowMode::Saturating => Some(operand.saturating_neg()),
+                OverflowMode::Wrapping => Some(operand.wrapping_neg()),
+            },
+            // a percentage of an integer is generally not an integer
+            Operator::Percent => return Err(Error::InvalidOperator { op: self.to_string() }),
+            Operator::Abs => match mode {
+                OverflowMode::Checked => operand.checked_abs(),
+                OverflowMode::Saturating => Some(operand.saturating_abs()),
+                OverflowMode::Wrapping => Some(operand.wrapping_abs()),
+            },
+            Operator::Not => Some((operand == 0) as i128),
+            _ => panic!("Operator::apply_unary_int() called on non-unary operator"),
+        };
+        result.ok_or(Error::Overflow)
+    }
+
+    /// applies the operator with integer arithmetic, handling overflow according to `options.overflow_mode`.
+    /// Division truncates toward zero.
+    pub(crate) fn apply_int(&self, left: i128, right: i128, options: &SolveOptions) -> Result<i128, Error> {
+        self.apply_int_in_mode(left, right, options, options.overflow_mode)
+    }
+
+    fn apply_int_in_mode(&self, left: i128, right: i128, options: &SolveOptions, mode: OverflowMode) -> Result<i128, Error> {
+        if right == 0 && matches!(self, Operator::Div | Operator::IntDiv | Operator::Mod) {
+            return Err(Error::DivByZero);
+        }
+        let result = match self {
+            Operator::Add => match mode {
+                OverflowMode::Checked => left.checked_add(right),
+                OverflowMode::Saturating => Some(left.saturating_add(right)),
+                OverflowMode::Wrapping => Some(left.wrapping_add(right)),
+            },
+            Operator::Sub => match mode {
+                OverflowMode::Checked => left.checked_sub(right),
+                OverflowMode::Saturating => Some(left.saturating_sub(right)),
+                OverflowMode::Wrapping => Some(left.wrapping_sub(right)),
+            },
+            Operator::Mul => match mode {
+                OverflowMode::Checked => left.checked_mul(right),
+                OverflowMode::Saturating => Some(left.saturating_mul(right)),
+                OverflowMode::Wrapping => Some(left.wrapping_mul(right)),
+            },
+            // only i128::MIN / -1 can overflow, and integer division already truncates toward zero
+            Operator::Div | Operator::IntDiv => match mode {
+                OverflowMode::Checked => left.checked_div(right),
+                OverflowMode::Saturating => Some(left.saturating_div(right)),
+                OverflowMode::Wrapping => Some(left.wrapping_div(right)),
+            },
+            Operator::Mod => match (options.modulo_mode, mode) {
+                (ModuloMode::Truncated, OverflowMode::Checked) => left.checked_rem(right),
+                (ModuloMode::Euclidean, OverflowMode::Checked) => left.checked_rem_euclid(right),
+                // the remainder of i128::MIN / -1 is 0, it's only the quotient that overflows
+                (ModuloMode::Truncated, _) => Some(left.wrapping_rem(right)),
+                (ModuloMode::Euclidean, _) => Some(left.wrapping_rem_euclid(right)),
+            },
+            Operator::Pow => {
+                if right < 0 {
+                    return Err(Error::NegativeExponent);
+                }
+                pow_int(left, right as u128, mode)
+            }
+            Operator::Eq => Some((left == right) as i128),
+            Operator::NotEq => Some((left != right) as i128),
+            Operator::Less => Some((left < right) as i128),
+            Operator::Greater => Some((left > right) as i128),
+            Operator::LessEq => Some((left <= right) as i128),
+            Operator::GreaterEq => Some((left >= right) as i128),
+            Operator::Comma => Some(right),
+            Operator::And => Some((left != 0 && right != 0) as i128),
+            Operator::Or => Some((left != 0 || right != 0) as i128),
+            _ => panic!("Operator::apply_int() called on unary operator"),
+        };
+        result.ok_or(Error::Overflow)
+    }
+}
+
+/// raises `base` to the power of `exp`, which may be larger than the `u32` the standard library accepts
+fn pow_int(base: i128, exp: u128, mode: OverflowMode) -> Option<i128> {
+    let mut result: i128 = 1;
+    let mut base = base;
+    let mut exp = exp;
+    // exponentiation by squaring, which only squares the base while there is still a bit of the exponent left
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = match mode {
+                OverflowMode::Checked => result.checked_mul(base)?,
+                OverflowMode::Saturating => result.saturating_mul(base),
+                OverflowMode::Wrapping => result.wrapping_mul(base),
+            };
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = match mode {
+                OverflowMode::Checked => base.checked_mul(base)?,
+                OverflowMode::Saturating => base.saturating_mul(base),
+                OverflowMode::Wrapping => base.wrapping_mul(base),
+            };
+        }
+    }
+    Some(result)
 }
 
 impl Display for Operator {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         match self {
-            Operator::LeftParen => write!(f, "("),
-            Operator::RightParen => write!(f, ")"),
             Operator::Add => write!(f, "+"),
             Operator::Sub => write!(f, "-"),
             Operator::Mul => write!(f, "*"),
             Operator::Div => write!(f, "/"),
             Operator::Mod => write!(f, "%"),
-            Operator::Assign => write!(f, "="),
+            Operator::IntDiv => write!(f, "//"),
             Operator::Pow => write!(f, "^"),
+            Operator::Neg => write!(f, "-"),
+            Operator::Percent => write!(f, "%"),
+            Operator::Abs => write!(f, "abs"),
+            Operator::Eq => write!(f, "=="),
+            Operator::NotEq => write!(f, "!="),
+            Operator::Less => write!(f, "<"),
+            Operator::Greater => write!(f, ">"),
+            Operator::LessEq => write!(f, "<="),
+            Operator::GreaterEq => write!(f, ">="),
+            Operator::Comma => write!(f, ","),
+            Operator::And => write!(f, "&&"),
+            Operator::Or => write!(f, "||"),
+            Operator::Not => write!(f, "!"),
         }
     }
 }
\ No newline at end of file
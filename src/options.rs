@@ -0,0 +1,287 @@
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+
+/// How the modulo operator (`%`) handles negative operands.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum ModuloMode {
+    /// The remainder takes the sign of the dividend, matching Rust's `%` (i.e. `-7 % 3 = -1`).
+    #[default]
+    Truncated,
+    /// The remainder is always non-negative, matching `f64::rem_euclid` (i.e. `-7 % 3 = 2`).
+    Euclidean,
+}
+
+/// What happens when an integer operation overflows, see `evaluate_int_with_options`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum OverflowMode {
+    /// Returns `Error::Overflow`.
+    #[default]
+    Checked,
+    /// Clamps the result to the smallest or largest `i128` (i.e. `i128::MAX + 1 = i128::MAX`).
+    Saturating,
+    /// Wraps the result around the bounds of an `i128` (i.e. `i128::MAX + 1 = i128::MIN`).
+    Wrapping,
+}
+
+/// What dividing by zero (with `/` or `%`) results in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum DivByZeroMode {
+    /// Returns `Error::DivByZero`.
+    #[default]
+    Error,
+    /// Results in `f64::NAN`.
+    NaN,
+    /// Results in positive or negative infinity depending on the signs of the operands (i.e. `-1 / 0 = -inf`),
+    /// following floating point division. `0 / 0` and `x % 0` are still NaN, as they have no sensible infinite value.
+    Infinity,
+}
+
+/// Options that change how an expression is evaluated.
+/// `SolveOptions::default()` matches the behavior of `evaluate` and `evaluate_with_defined`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolveOptions {
+    /// How the modulo operator handles negative operands.
+    pub modulo_mode: ModuloMode,
+    /// Treats `%` as a percent sign instead of the modulo operator, so `50%` is `0.5`
+    /// and `200 * 5%` is `10`.
+    ///
+    /// A `%` directly following an operand (a number, variable, function call or closing
+    /// parenthesis) divides that operand by 100. This means `10 % 3` is an error in this mode,
+    /// as `10%` is followed by another number without an operator in between.
+    pub percent_mode: bool,
+    /// Uses `,` as the decimal point instead of `.`, so `3,14` is `3.14`.
+    ///
+    /// As `,` can then no longer separate function arguments, `;` is used instead
+    /// (i.e. `max(1,5; 2)`), and `.` is no longer accepted in numbers.
+    pub decimal_comma: bool,
+    /// The deepest function calls, parentheses and absolute value bars can be nested,
+    /// which keeps untrusted input from overflowing the stack. Calls to functions defined
+    /// from expressions (see `Calculator::define_function`) count towards the depth as well.
+    ///
+    /// Exceeding it returns `Error::DepthExceeded`. Defaults to 256.
+    pub max_depth: usize,
+    /// What happens when integer arithmetic overflows. Only used when evaluating integers.
+    pub overflow_mode: OverflowMode,
+    /// Always allows identifiers in the input, reporting any variable or function that isn't defined
+    /// as `Error::UndefinedVariable` or `Error::UndefinedFunction`. Without this, identifiers are only
+    /// allowed when definitions or functions are given, and any that are left undefined are reported
+    /// as `Error::InvalidCharacter` or `Error::InvalidOperand`.
+    pub strict_identifiers: bool,
+    /// How close two values have to be for comparisons to consider them equal, relative to the larger
+    /// of the two values (or 1, if both are smaller than that). This makes `0.1 + 0.2 == 0.3` and
+    /// `0.1 + 0.2 <= 0.3` true and `0.1 + 0.2 > 0.3` false, even though the left side is slightly off as a float.
+    /// Infinite and NaN values are always compared exactly.
+    ///
+    /// Defaults to `f64::EPSILON`. Setting it to 0 compares values exactly.
+    pub float_epsilon: f64,
+    /// Remembers the result of each function call during an evaluation, so calling a function again
+    /// with the same arguments (i.e. `sqrt(2)` appearing several times) doesn't call it again.
+    ///
+    /// Only enable this when every function always returns the same result for the same arguments,
+    /// as functions like `rand()` would otherwise return the same value for every call.
+    pub memoize: bool,
+    /// The only functions the input is allowed to call, which is useful when evaluating untrusted input
+    /// with functions that shouldn't be available to it. Calling any other function returns
+    /// `Error::FunctionNotAllowed`, even if it is defined.
+    ///
+    /// Only calls written in the input are checked, not calls made by the body of a function
+    /// defined from an expression (see `Calculator::define_function`). Defaults to `None`, allowing every function.
+    pub allowed_functions: Option<BTreeSet<String>>,
+    /// The most operator applications and function calls an evaluation can do, which keeps
+    /// untrusted input from doing an unreasonable amount of work.
+    ///
+    /// Exceeding it returns `Error::OperationLimitExceeded`. Defaults to `usize::MAX`.
+    pub max_operations: usize,
+    /// What dividing by zero results in. Integer arithmetic (see `evaluate_int`) always returns `Error::DivByZero`.
+    pub div_by_zero: DivByZeroMode,
+    /// Evaluates the parts of an expression that don't depend on any variables or functions when it is compiled
+    /// (see `Expression::compile_with_options`), so they aren't evaluated again every time it is evaluated.
+    /// Errors in those parts, like dividing by zero, are then returned when compiling.
+    pub fold_constants: bool,
+    /// Accepts an SI suffix right after a number, multiplying it by `k` (1000), `M` (10^6), `G` (10^9), `T` (10^12),
+    /// `m` (1/1000) or `u` (1/10^6), so `2k` is 2000 and `500m` is 0.5.
+    ///
+    /// The suffix takes precedence over a variable with the same name, so `2m` is 0.002 even if `m` is defined.
+    /// A suffix followed by more letters is not a suffix, so `2km` is still the number 2 followed by `km`.
+    pub si_suffixes: bool,
+    /// Accepts a binary (IEC) suffix right after a number, multiplying it by `Ki` (1024), `Mi` (1024^2),
+    /// `Gi` (1024^3) or `Ti` (1024^4), so `1Ki` is 1024. This can be used along with `si_suffixes`.
+    ///
+    /// Like SI suffixes, a suffix followed by more letters is not a suffix, so `1Kib` is the number 1
+    /// followed by the identifier `Kib`, and a suffix without a number before it is just an identifier.
+    pub binary_suffixes: bool,
+    /// Treats a "call" of a variable as multiplying it by the parenthesized expression after it,
+    /// so `x(2 + 3)` is `x * (2 + 3)` when `x` is a variable.
+    ///
+    /// This only applies to names that are defined as variables and aren't registered as functions, so a
+    /// function with the same name as a variable is still called. It also needs the variables to be known while
+    /// parsing, so it applies to `evaluate_with_options` (and functions that use it, like `evaluate_with_defined`),
+    /// but not to expressions that are compiled before their variables are given, like `Expression::compile`.
+    /// As with calls, the '(' must come right after the name, and it can't be used in function arguments.
+    pub implicit_mul_unknown_calls: bool,
+    /// Accepts `inf` and `nan` as numbers, for infinity and not a number (so `-inf` is negative infinity).
+    /// Arithmetic with them follows IEEE 754, so `inf + 1` is infinite and `nan == nan` is false.
+    ///
+    /// This is off by default, as a `nan` anywhere in an expression quietly makes its result `nan`.
+    /// When it is on, the words take precedence over variables named `inf` or `nan`, though functions with those
+    /// names can still be called.
+    pub allow_special_floats: bool,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        Self {
+            modulo_mode: ModuloMode::default(),
+            percent_mode: false,
+            decimal_comma: false,
+            max_depth: 256,
+            overflow_mode: OverflowMode::default(),
+            strict_identifiers: false,
+            float_epsilon: f64::EPSILON,
+            memoize: false,
+            allowed_functions: None,
+            max_operations: usize::MAX,
+            div_by_zero: DivByZeroMode::default(),
+            fold_constants: false,
+            si_suffixes: false,
+            binary_suffixes: false,
+            implicit_mul_unknown_calls: false,
+            allow_special_floats: false,
+        }
+    }
+}
+
+impl SolveOptions {
+    /// Creates a builder for options, starting from the default options.
+    ///
+    /// # Usage Example:
+    /// ```
+    /// use calc_lib::{ModuloMode, SolveOptions};
+    ///
+    /// let options = SolveOptions::builder().euclidean_mod().max_depth(128).build();
+    /// assert_eq!(options.modulo_mode, ModuloMode::Euclidean);
+    /// assert_eq!(options.max_depth, 128);
+    /// ```
+    pub fn builder() -> SolveOptionsBuilder {
+        SolveOptionsBuilder { options: SolveOptions::default() }
+    }
+
+    /// returns true if the input is allowed to call the function, see `allowed_functions`
+    pub(crate) fn allows_function(&self, name: &str) -> bool {
+        self.allowed_functions.as_ref().is_none_or(|allowed| allowed.contains(name))
+    }
+}
+
+/// Builds `SolveOptions` one option at a time, see `SolveOptions::builder`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolveOptionsBuilder {
+    options: SolveOptions,
+}
+
+impl SolveOptionsBuilder {
+    /// Sets how the modulo operator handles negative operands.
+    pub fn modulo_mode(mut self, mode: ModuloMode) -> Self {
+        self.options.modulo_mode = mode;
+        self
+    }
+
+    /// Uses Euclidean modulo, so the remainder is never negative.
+    pub fn euclidean_mod(self) -> Self {
+        self.modulo_mode(ModuloMode::Euclidean)
+    }
+
+    /// Treats `%` as a percent sign, see `SolveOptions::percent_mode`.
+    pub fn percent_mode(mut self) -> Self {
+        self.options.percent_mode = true;
+        self
+    }
+
+    /// Uses `,` as the decimal point and `;` to separate arguments, see `SolveOptions::decimal_comma`.
+    pub fn decimal_comma(mut self) -> Self {
+        self.options.decimal_comma = true;
+        self
+    }
+
+    /// Sets how deep function calls, parentheses and absolute value bars can be nested.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.options.max_depth = max_depth;
+        self
+    }
+
+    /// Sets what happens when integer arithmetic overflows.
+    pub fn overflow_mode(mut self, mode: OverflowMode) -> Self {
+        self.options.overflow_mode = mode;
+        self
+    }
+
+    /// Reports undefined identifiers precisely, see `SolveOptions::strict_identifiers`.
+    pub fn strict_identifiers(mut self) -> Self {
+        self.options.strict_identifiers = true;
+        self
+    }
+
+    /// Sets how close two values have to be for comparisons to consider them equal.
+    pub fn float_epsilon(mut self, epsilon: f64) -> Self {
+        self.options.float_epsilon = epsilon;
+        self
+    }
+
+    /// Remembers the results of function calls during an evaluation, see `SolveOptions::memoize`.
+    pub fn memoize(mut self) -> Self {
+        self.options.memoize = true;
+        self
+    }
+
+    /// Only allows the input to call the given functions, see `SolveOptions::allowed_functions`.
+    pub fn allowed_functions<I: IntoIterator<Item = S>, S: Into<String>>(mut self, names: I) -> Self {
+        self.options.allowed_functions = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the most operator applications and function calls an evaluation can do.
+    pub fn max_operations(mut self, max_operations: usize) -> Self {
+        self.options.max_operations = max_operations;
+        self
+    }
+
+    /// Sets what dividing by zero results in.
+    pub fn div_by_zero(mut self, mode: DivByZeroMode) -> Self {
+        self.options.div_by_zero = mode;
+        self
+    }
+
+    /// Evaluates constant parts of expressions when compiling them, see `SolveOptions::fold_constants`.
+    pub fn fold_constants(mut self) -> Self {
+        self.options.fold_constants = true;
+        self
+    }
+
+    /// Accepts SI suffixes after numbers, see `SolveOptions::si_suffixes`.
+    pub fn si_suffixes(mut self) -> Self {
+        self.options.si_suffixes = true;
+        self
+    }
+
+    /// Accepts binary suffixes after numbers, see `SolveOptions::binary_suffixes`.
+    pub fn binary_suffixes(mut self) -> Self {
+        self.options.binary_suffixes = true;
+        self
+    }
+
+    /// Multiplies variables by a parenthesized expression right after them, see `SolveOptions::implicit_mul_unknown_calls`.
+    pub fn implicit_mul_unknown_calls(mut self) -> Self {
+        self.options.implicit_mul_unknown_calls = true;
+        self
+    }
+
+    /// Accepts `inf` and `nan` as numbers, see `SolveOptions::allow_special_floats`.
+    pub fn allow_special_floats(mut self) -> Self {
+        self.options.allow_special_floats = true;
+        self
+    }
+
+    /// Creates the options.
+    pub fn build(self) -> SolveOptions {
+        self.options
+    }
+}
@@ -1,12 +1,19 @@
-use std::fmt::{Display, Formatter};
-use crate::Error;
-use crate::lex::Token;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use crate::{Error, SolveOptions};
+use crate::lex::{Symbol, Token};
 use crate::operator::Operator;
+use crate::value::Value;
 
 #[derive(Debug, Clone)]
 pub(crate) struct ShuntedStackItem {
     operator: Option<Operator>,
     operand: Option<Token>,
+    /// whether this is a comparison that is followed by another in a chain, see `ShuntedStack::chain_copies`
+    chained: bool,
 }
 
 impl ShuntedStackItem {
@@ -14,6 +21,7 @@ impl ShuntedStackItem {
         Self {
             operator: None,
             operand: Some(statement),
+            chained: false,
         }
     }
 
@@ -21,9 +29,23 @@ impl ShuntedStackItem {
         Self {
             operator: Some(operator),
             operand: None,
+            chained: false,
         }
     }
 
+    /// a comparison that is followed by another in a chain, which gives its right operand to the next one
+    pub(crate) fn new_chained(operator: Operator) -> Self {
+        Self {
+            operator: Some(operator),
+            operand: None,
+            chained: true,
+        }
+    }
+
+    pub(crate) fn is_chained(&self) -> bool {
+        self.chained
+    }
+
     pub(crate) fn is_operator(&self) -> bool {
         self.operator.is_some()
     }
@@ -42,7 +64,7 @@ impl ShuntedStackItem {
 }
 
 impl Display for ShuntedStackItem {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         if self.is_operator() {
             write!(f, "{}", self.get_operator().unwrap())
         } else {
@@ -80,10 +102,208 @@ impl ShuntedStack {
     pub(crate) fn len(&self) -> usize {
         self.items.len()
     }
+
+    /// the operands in the stack, in the order they are evaluated
+    pub(crate) fn operands(&self) -> impl Iterator<Item = &Token> {
+        self.items.iter().filter_map(ShuntedStackItem::get_operand)
+    }
+
+    /// a copy of the stack that iterates from the start, however far this one has been iterated
+    pub(crate) fn rewound(&self) -> Self {
+        Self {
+            items: self.items.clone(),
+            current_iter: 0
+        }
+    }
+
+    /// for each `&&` and `||`, the index its right operand starts at and the index of the operator.
+    /// The right operand is skipped when the left one decides the result, so `0 && x` doesn't need `x`
+    pub(crate) fn short_circuits(&self) -> Vec<(usize, usize)> {
+        let mut jumps = Vec::new();
+        for (end, item) in self.items.iter().enumerate() {
+            if !matches!(item.get_operator(), Some(Operator::And | Operator::Or)) {
+                continue;
+            }
+            if let Some(start) = self.operand_start(end) {
+                jumps.push((start, end));
+            }
+        }
+        jumps
+    }
+
+    /// whether each item is part of the right operand of a `&&` or `||`, and so might not be evaluated
+    pub(crate) fn conditional_items(&self) -> Vec<bool> {
+        let mut conditional = vec![false; self.items.len()];
+        for (start, end) in self.short_circuits() {
+            conditional[start..end].fill(true);
+        }
+        conditional
+    }
+
+    /// for each comparison in a chain that is followed by another, the range of the copy of its right operand,
+    /// which starts at the first operand after it (after the `&&` joining the chain before it, if there is one).
+    /// `a < b < c` is `a b < b c < &&`, and the value of `b` from the first comparison is used in place of the copy,
+    /// so it is only evaluated once
+    pub(crate) fn chain_copies(&self) -> Vec<(usize, usize)> {
+        let mut copies = Vec::new();
+        for (index, item) in self.items.iter().enumerate() {
+            if let Some(start) = self.operand_start(index).filter(|_| item.is_chained()) {
+                let copy = (index + 1..self.items.len()).find(|x| self.items[*x].is_operand()).unwrap_or(self.items.len());
+                copies.push((copy, copy + index - start));
+            }
+        }
+        copies
+    }
+
+    /// the places an evaluation of the stack can skip ahead
+    pub(crate) fn jumps(&self) -> Jumps {
+        Jumps {
+            short_circuits: self.short_circuits(),
+            chain_copies: self.chain_copies(),
+            conditional: self.conditional_items(),
+        }
+    }
+
+    /// the index the operand that ends right before `index` starts at, or None if the stack doesn't have one there
+    fn operand_start(&self, index: usize) -> Option<usize> {
+        let mut needed = 1;
+        let mut start = index;
+        while needed > 0 {
+            start = start.checked_sub(1)?;
+            match self.items[start].get_operator() {
+                None => needed -= 1,
+                Some(op) if !op.is_unary() => needed += 1,
+                Some(_) => {}
+            }
+        }
+        Some(start)
+    }
+}
+
+/// the places an evaluation of a stack can skip ahead. They only depend on the stack's operators,
+/// so they can be found once for a stack that is evaluated many times
+#[derive(Debug, Clone)]
+pub(crate) struct Jumps {
+    /// see `ShuntedStack::short_circuits`
+    pub(crate) short_circuits: Vec<(usize, usize)>,
+    /// see `ShuntedStack::chain_copies`
+    pub(crate) chain_copies: Vec<(usize, usize)>,
+    /// see `ShuntedStack::conditional_items`
+    pub(crate) conditional: Vec<bool>,
+}
+
+/// An item of an expression in postfix (reverse polish) notation, see `to_postfix`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostfixItem {
+    /// A number to push onto the stack
+    Operand(Value),
+    /// A variable to push onto the stack
+    Variable(String),
+    /// An operator to apply to the top of the stack, which takes one operand for `Neg`, `Not`, `Percent` and `Abs`
+    /// and two otherwise
+    Operator(Operator),
+    /// A function to call with the given number of arguments from the top of the stack
+    Function(String, usize),
+}
+
+impl ShuntedStack {
+    /// converts the stack to its public form, putting the arguments of each function call before it
+    pub(crate) fn to_items(&self) -> Vec<PostfixItem> {
+        let mut items = Vec::new();
+        for item in &self.items {
+            match item.get_operand() {
+                Some(operand) => push_operand(operand, &mut items),
+                None => items.push(PostfixItem::Operator(item.get_operator().unwrap().clone())),
+            }
+        }
+        items
+    }
+}
+
+fn push_operand(operand: &Token, items: &mut Vec<PostfixItem>) {
+    match operand {
+        Token::Num(n) => items.push(PostfixItem::Operand(*n)),
+        Token::Identifier(name) => items.push(PostfixItem::Variable(name.to_string())),
+        Token::Function(name, args) => {
+            for arg in args {
+                push_operand(arg, items);
+            }
+            items.push(PostfixItem::Function(name.to_string(), args.len()));
+        }
+        Token::Group(stack) => items.append(&mut stack.to_items()),
+        Token::Operator(op) => items.push(PostfixItem::Operator(op.clone())),
+        // grouping symbols are never left in the stack by `shunting_yard`
+        Token::Symbol(_) => {}
+    }
+}
+
+/// a part of a stack while folding constants, see `fold_constants`
+enum Folded {
+    /// a part that only depends on numbers, which has been evaluated
+    Value(Value),
+    /// a part that depends on a variable or function, which is left to be evaluated later
+    Items(Vec<ShuntedStackItem>),
+}
+
+impl Folded {
+    fn into_items(self) -> Vec<ShuntedStackItem> {
+        match self {
+            Folded::Value(value) => vec![ShuntedStackItem::new_operand(Token::Num(value))],
+            Folded::Items(items) => items,
+        }
+    }
+}
+
+/// evaluates the parts of the stack that don't depend on any variables or functions ahead of time,
+/// so `2 3 * x +` becomes `6 x +`. Errors in those parts (i.e. dividing by zero) are returned right away,
+/// and a stack that can't be evaluated is returned unchanged for evaluating it to report the error.
+pub(crate) fn fold_constants(stack: &ShuntedStack, options: &SolveOptions) -> Result<ShuntedStack, Error> {
+    let mut parts = Vec::new();
+    for item in &stack.items {
+        if let Some(operand) = item.get_operand() {
+            parts.push(match operand {
+                Token::Num(n) => Folded::Value(*n),
+                _ => Folded::Items(vec![item.clone()]),
+            });
+            continue;
+        }
+
+        let op = item.get_operator().unwrap();
+        let folded = if op.is_unary() {
+            match parts.pop() {
+                Some(Folded::Value(n)) => Folded::Value(op.apply_unary_value(n)?),
+                Some(Folded::Items(mut items)) => {
+                    items.push(item.clone());
+                    Folded::Items(items)
+                }
+                None => return Ok(stack.clone()),
+            }
+        } else {
+            match (parts.pop(), parts.pop()) {
+                (Some(Folded::Value(right)), Some(Folded::Value(left))) => Folded::Value(op.apply_value(left, right, options)?),
+                (Some(right), Some(left)) => {
+                    let mut items = left.into_items();
+                    items.append(&mut right.into_items());
+                    items.push(item.clone());
+                    Folded::Items(items)
+                }
+                _ => return Ok(stack.clone()),
+            }
+        };
+        parts.push(folded);
+    }
+
+    let mut folded = ShuntedStack::new();
+    for part in parts {
+        for item in part.into_items() {
+            folded.push(item);
+        }
+    }
+    Ok(folded)
 }
 
 impl Display for ShuntedStack {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         let mut result = String::new();
         for item in self.items.iter() {
             result.push_str(&format!("{}", item));
@@ -102,32 +322,57 @@ impl Iterator for ShuntedStack {
     }
 }
 
-pub(crate) fn shunting_yard(tokens: &mut Vec<Token>) -> Result<ShuntedStack, Error> {
+/// an item of the operator stack in `shunting_yard`, which is an operator or a group that is still open
+#[derive(Debug, Clone, PartialEq)]
+enum Pending {
+    Operator(Operator),
+    LeftParen,
+    Bar,
+}
+
+pub(crate) fn shunting_yard(tokens: &mut Vec<Token>, options: &SolveOptions) -> Result<ShuntedStack, Error> {
     let mut postfix = ShuntedStack::new();
-    let mut op_stack: Vec<Operator> = Vec::new();
+    let mut op_stack: Vec<Pending> = Vec::new();
 
     let mut last_op: Option<Operator> = None;
+    // whether the last token was a ')', after which the input continues like it does after an operand
+    let mut closed_paren = false;
     let mut negative = false;
     let mut last_was_ident = false;
+    // how many parentheses and absolute value bars are currently open
+    let mut depth = 0;
+    // the text of the last operand, for reporting two operands in a row
+    let mut last_operand = String::new();
+    // for each comparison pushed onto the operator stack, its position on the stack and the index
+    // in the output where its right operand starts, which is copied if another comparison follows it
+    let mut comparison_starts: Vec<(usize, usize)> = Vec::new();
 
-    let first = tokens.get(0).unwrap();
-    if let Token::Operator(op) = &first {
-        match op {
-            Operator::Sub => {
-                negative = true;
-            }
-            Operator::LeftParen => {}
-            _ => {
-                return Err(Error::InvalidLeadingOperator { op: op.to_string() });
-            }
+    // function calls without arguments are a single token, so these can only be parentheses with nothing in them
+    let empty_parens = tokens.windows(2).any(|pair| matches!(pair,
+        [Token::Symbol(Symbol::LeftParen), Token::Symbol(Symbol::RightParen)]));
+    if empty_parens {
+        return Err(Error::EmptyParentheses);
+    }
+
+    match tokens.first() {
+        Some(Token::Operator(Operator::Sub | Operator::Add | Operator::Not)) => {}
+        Some(Token::Symbol(Symbol::RightParen)) => {
+            return Err(Error::MismatchedParentheses { found: ')', missing: '(' });
         }
+        Some(Token::Operator(op)) => {
+            return Err(Error::InvalidLeadingOperator { op: op.to_string() });
+        }
+        Some(Token::Symbol(symbol @ (Symbol::Assign | Symbol::CompoundAssign(_)))) => {
+            return Err(Error::InvalidLeadingOperator { op: symbol.to_string() });
+        }
+        _ => {}
     }
 
     for token in tokens {
         match &token {
             Token::Num(_) => {
-                if last_was_ident {
-                    return Err(Error::InvalidExpression { reason: "Two identifiers or numbers found in a row".to_string() });
+                if last_was_ident || closed_paren {
+                    return Err(Error::AdjacentOperands { left: last_operand, right: token.to_string() });
                 }
                 let mut t = token.clone();
                 if negative {
@@ -135,85 +380,246 @@ pub(crate) fn shunting_yard(tokens: &mut Vec<Token>) -> Result<ShuntedStack, Err
                         t = Token::Num(-x);
                     }
                 }
+                last_operand = t.to_string();
                 postfix.push(ShuntedStackItem::new_operand(t));
                 last_was_ident = true;
                 last_op = None;
+                closed_paren = false;
                 negative = false;
             }
             Token::Identifier(_) => {
-                if last_was_ident {
-                    return Err(Error::InvalidExpression { reason: "Two identifiers or numbers found in a row".to_string() });
+                if last_was_ident || closed_paren {
+                    return Err(Error::AdjacentOperands { left: last_operand, right: token.to_string() });
                 }
+                last_operand = if negative { format!("-{}", token) } else { token.to_string() };
                 postfix.push(ShuntedStackItem::new_operand(token.clone()));
+                if negative {
+                    postfix.push(ShuntedStackItem::new_operator(Operator::Neg));
+                }
                 last_op = None;
+                closed_paren = false;
                 last_was_ident = true;
                 negative = false;
             }
-            Token::Function(_, _) => {
-                if last_was_ident {
-                    return Err(Error::InvalidExpression { reason: "Two identifiers or numbers found in a row".to_string() });
+            Token::Function(_, _) | Token::Group(_) => {
+                if last_was_ident || closed_paren {
+                    return Err(Error::AdjacentOperands { left: last_operand, right: token.to_string() });
                 }
+                last_operand = if negative { format!("-{}", token) } else { token.to_string() };
                 postfix.push(ShuntedStackItem::new_operand(token.clone()));
+                if negative {
+                    postfix.push(ShuntedStackItem::new_operator(Operator::Neg));
+                }
                 last_op = None;
+                closed_paren = false;
                 last_was_ident = true;
                 negative = false;
             }
+            // assignments are split off before the rest of a statement is parsed, see `evaluate_program`
+            Token::Symbol(Symbol::Assign | Symbol::CompoundAssign(_)) => return Err(Error::InvalidOperator { op: token.to_string() }),
+            Token::Symbol(Symbol::LeftParen) => {
+                // a negated group is negated once it has been evaluated
+                if negative {
+                    op_stack.push(Pending::Operator(Operator::Neg));
+                }
+                op_stack.push(Pending::LeftParen);
+                if closed_paren {
+                    return Err(Error::AdjacentOperands { left: last_operand, right: "(...)".to_string() });
+                }
+                if last_was_ident {
+                    return Err(Error::MissingOperator);
+                }
+                depth += 1;
+                if depth > options.max_depth {
+                    return Err(Error::DepthExceeded);
+                }
+                last_op = None;
+                closed_paren = false;
+                last_was_ident = false;
+                negative = false;
+            }
+            Token::Symbol(Symbol::RightParen) => {
+                // an operand is expected after an operator, including a unary one like in `(-)`
+                if !last_was_ident && !closed_paren {
+                    return Err(Error::Expected { expected: "operand".to_string(), found: token.to_string() });
+                }
+                last_was_ident = false;
+                let mut found = false;
+                while let Some(op2) = op_stack.pop() {
+                    match op2 {
+                        Pending::LeftParen => {
+                            found = true;
+                            break;
+                        }
+                        Pending::Bar => return Err(Error::MismatchedParentheses { found: ')', missing: '|' }),
+                        Pending::Operator(op2) => postfix.push(ShuntedStackItem::new_operator(op2)),
+                    }
+                }
+
+                if !found {
+                    return Err(Error::MismatchedParentheses { found: ')', missing: '(' });
+                }
+                depth -= 1;
+
+                // the closed group is an operand
+                last_operand = "(...)".to_string();
+                last_op = None;
+                closed_paren = true;
+                negative = false;
+            }
+            // a '|' where an operand is expected opens an absolute value group, anywhere else
+            // it closes the innermost open group. This means `|a| + |b|` is two groups, while
+            // a nested group has to start right after an operator or another '|', i.e. `||a| - b|`
+            Token::Symbol(Symbol::Bar) if !last_was_ident && !closed_paren => {
+                if negative {
+                    op_stack.push(Pending::Operator(Operator::Neg));
+                }
+                op_stack.push(Pending::Bar);
+                depth += 1;
+                if depth > options.max_depth {
+                    return Err(Error::DepthExceeded);
+                }
+                last_op = None;
+                negative = false;
+            }
+            Token::Symbol(Symbol::Bar) => {
+                let mut found = false;
+                while let Some(op2) = op_stack.pop() {
+                    match op2 {
+                        Pending::Bar => {
+                            found = true;
+                            break;
+                        }
+                        Pending::LeftParen => return Err(Error::MismatchedParentheses { found: '|', missing: ')' }),
+                        Pending::Operator(op2) => postfix.push(ShuntedStackItem::new_operator(op2)),
+                    }
+                }
+
+                if !found {
+                    return Err(Error::MismatchedParentheses { found: '|', missing: '|' });
+                }
+                postfix.push(ShuntedStackItem::new_operator(Operator::Abs));
+                depth -= 1;
+
+                // the closed group is an operand
+                last_operand = "|...|".to_string();
+                last_was_ident = true;
+                last_op = None;
+                closed_paren = false;
+            }
             Token::Operator(op) => {
                 match op {
-                    Operator::LeftParen => {
-                        op_stack.push(op.clone());
-                        if last_was_ident {
-                            return Err(Error::MissingOperator);
+                    // the comma operator is only allowed inside parentheses, where it ends the expression
+                    // before it, so `(a + b, c)` is `(a + b), c`
+                    Operator::Comma => {
+                        if !last_was_ident && !closed_paren {
+                            return Err(Error::Expected { expected: "operand".to_string(), found: op.to_string() });
                         }
-                        last_op = None;
+                        while let Some(op2) = op_stack.last() {
+                            match op2 {
+                                Pending::LeftParen => break,
+                                Pending::Bar => return Err(Error::InvalidCharacter { c: ',' }),
+                                Pending::Operator(op2) => {
+                                    postfix.push(ShuntedStackItem::new_operator(op2.clone()));
+                                    op_stack.pop();
+                                }
+                            }
+                        }
+                        if op_stack.is_empty() {
+                            return Err(Error::InvalidCharacter { c: ',' });
+                        }
+                        op_stack.push(Pending::Operator(Operator::Comma));
+                        last_op = Some(Operator::Comma);
+                        closed_paren = false;
                         last_was_ident = false;
                         negative = false;
                     }
-                    Operator::RightParen => {
-                        last_was_ident = false;
-                        let mut found = false;
-                        while let Some(op2) = op_stack.pop() {
-                            if op2 == Operator::LeftParen {
-                                found = true;
-                                break;
+                    _ => {
+                        // handle unary operators, which appear where an operand is expected:
+                        // at the start of the input, after another operator or after a '('
+                        if !last_was_ident && !closed_paren {
+                            match op {
+                                // each unary minus flips the sign of the operand
+                                Operator::Sub => {
+                                    negative = !negative;
+                                    continue;
+                                }
+                                // unary plus does not change the operand
+                                Operator::Add => continue,
+                                // a logical not is applied once its operand has been evaluated,
+                                // after any minus before it
+                                Operator::Not => {
+                                    if negative {
+                                        op_stack.push(Pending::Operator(Operator::Neg));
+                                    }
+                                    op_stack.push(Pending::Operator(Operator::Not));
+                                    last_op = Some(Operator::Not);
+                                    negative = false;
+                                    continue;
+                                }
+                                _ if last_op.is_some() => return Err(Error::InvalidOperator { op: op.to_string() }),
+                                _ => return Err(Error::InvalidLeadingOperator { op: op.to_string() }),
                             }
-                            postfix.push(ShuntedStackItem::new_operator(op2));
                         }
 
-                        if !found {
-                            return Err(Error::MismatchedParentheses { found: ')', missing: '(' });
+                        // there is no factorial, so a '!' after an operand is always an error
+                        if *op == Operator::Not {
+                            return Err(Error::InvalidOperator { op: op.to_string() });
                         }
 
-                        last_op = Some(op.clone());
-                        negative = false;
-                    }
-                    _ => {
-                        // handle unary operators
-                        if last_op.is_some() && last_op.as_ref().unwrap() != &Operator::RightParen {
-                            if *op == Operator::Sub {
-                                negative = true;
-                                last_was_ident = false;
-                                continue;
-                            } else if last_op.as_ref().unwrap().clone() != Operator::LeftParen
-                                && last_op.as_ref().unwrap().clone() != Operator::RightParen {
-                                return Err(Error::InvalidOperator { op: op.to_string() });
-                            }
+                        // in percent mode, '%' applies to the operand before it
+                        if *op == Operator::Mod && options.percent_mode {
+                            postfix.push(ShuntedStackItem::new_operator(Operator::Percent));
+                            last_operand.push('%');
+                            continue;
                         }
 
                         last_was_ident = false;
 
                         // handle normal operators
-                        while let Some(op2) = op_stack.last() {
-                            if *op2 == Operator::LeftParen {
+                        let mut chained = false;
+                        while let Some(Pending::Operator(op2)) = op_stack.last() {
+                            if op.is_comparison() && op2.is_comparison() {
+                                chained = true;
                                 break;
                             }
-                            if op2.precedence() <= op.precedence() {
+                            // operators of equal precedence are grouped left to right,
+                            // except for right associative operators like '^'
+                            if op2.precedence() < op.precedence()
+                                || (op2.precedence() == op.precedence() && op.is_right_associative()) {
                                 break
                             }
-                            postfix.push(ShuntedStackItem::new_operator(op_stack.pop().unwrap()));
+                            postfix.push(ShuntedStackItem::new_operator(op2.clone()));
+                            op_stack.pop();
                         }
-                        op_stack.push(op.clone());
+                        if chained {
+                            // a chain of comparisons is joined with `&&`, so `a < b < c` is `a < b && b < c`.
+                            // The operand between them is copied to be the left operand of the next comparison,
+                            // and the comparison before it is marked so the copy isn't evaluated again
+                            let position = op_stack.len() - 1;
+                            let start = comparison_starts.iter().rev().find(|(at, _)| *at == position).unwrap().1;
+                            let middle = postfix.items[start..].to_vec();
+                            if let Some(Pending::Operator(op2)) = op_stack.pop() {
+                                postfix.push(ShuntedStackItem::new_chained(op2));
+                            }
+                            while let Some(Pending::Operator(op2)) = op_stack.last() {
+                                if op2.precedence() < Operator::And.precedence() {
+                                    break;
+                                }
+                                postfix.push(ShuntedStackItem::new_operator(op2.clone()));
+                                op_stack.pop();
+                            }
+                            op_stack.push(Pending::Operator(Operator::And));
+                            for item in middle {
+                                postfix.push(item);
+                            }
+                        }
+                        if op.is_comparison() {
+                            comparison_starts.push((op_stack.len(), postfix.len()));
+                        }
+                        op_stack.push(Pending::Operator(op.clone()));
                         last_op = Some(op.clone());
+                        closed_paren = false;
                         negative = false;
                     }
                 }
@@ -221,12 +627,23 @@ pub(crate) fn shunting_yard(tokens: &mut Vec<Token>) -> Result<ShuntedStack, Err
         }
     }
 
+    // the input can't end where an operand is expected, i.e. after a binary operator
+    if !last_was_ident && !closed_paren {
+        // a group that was opened with nothing after it is reported as never being closed
+        match op_stack.last() {
+            Some(Pending::LeftParen) => return Err(Error::MismatchedParentheses { found: '(', missing: ')' }),
+            Some(Pending::Bar) => return Err(Error::MismatchedParentheses { found: '|', missing: '|' }),
+            _ => return Err(Error::UnexpectedEOI),
+        }
+    }
+
     while let Some(op) = op_stack.pop() {
-        if op == Operator::LeftParen {
-            return Err(Error::MismatchedParentheses { found: '(', missing: ')' });
+        match op {
+            Pending::LeftParen => return Err(Error::MismatchedParentheses { found: '(', missing: ')' }),
+            Pending::Bar => return Err(Error::MismatchedParentheses { found: '|', missing: '|' }),
+            Pending::Operator(op) => postfix.push(ShuntedStackItem::new_operator(op)),
         }
-        postfix.push(ShuntedStackItem::new_operator(op));
     }
 
     Ok(postfix)
-}
\ No newline at end of file
+}
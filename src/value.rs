@@ -0,0 +1,118 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt::{Display, Formatter};
+use core::ops::Neg;
+use crate::math;
+
+/// A number in an expression, which keeps integers separate from floating point numbers
+/// so integer arithmetic can be exact. See `evaluate_value`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    /// An integer, from integer literals and operations on them that have an exact integer result
+    Int(i128),
+    /// A floating point number
+    Float(f64),
+}
+
+impl Value {
+    /// Converts the value to a float, which may lose precision for large integers.
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Value::Int(i) => i as f64,
+            Value::Float(f) => f,
+        }
+    }
+
+    /// Formats the value rounded to `figs` significant figures (at least 1), without an exponent.
+    /// Zeros after the decimal point are kept when they are significant.
+    ///
+    /// ```
+    /// use calc_lib::Value;
+    ///
+    /// assert_eq!(Value::Int(123456).format_sig_figs(3), "123000");
+    /// assert_eq!(Value::Float(0.0012345).format_sig_figs(2), "0.0012");
+    /// assert_eq!(Value::Float(2.5).format_sig_figs(3), "2.50");
+    /// ```
+    pub fn format_sig_figs(&self, figs: usize) -> String {
+        let figs = figs.max(1);
+        match *self {
+            Value::Int(i) => {
+                let sign = if i < 0 { "-" } else { "" };
+                let digits = i.unsigned_abs().to_string();
+                if digits.len() <= figs {
+                    return format!("{}{}", sign, digits);
+                }
+                // rounding with integers keeps values larger than 2^53 exact
+                let zeros = digits.len() - figs;
+                let divisor = 10u128.pow(zeros as u32);
+                let (quotient, remainder) = (i.unsigned_abs() / divisor, i.unsigned_abs() % divisor);
+                let rounded = if remainder >= divisor - remainder { quotient + 1 } else { quotient };
+                format!("{}{}{}", sign, rounded, "0".repeat(zeros))
+            }
+            Value::Float(f) if !f.is_finite() => f.to_string(),
+            Value::Float(0.0) => format!("0{}", if figs > 1 { format!(".{}", "0".repeat(figs - 1)) } else { String::new() }),
+            Value::Float(f) => {
+                // scientific notation does the rounding, i.e. `1.2e-3`, which is then written out in full
+                let scientific = format!("{:.*e}", figs - 1, f.abs());
+                let (mantissa, exponent) = scientific.split_once('e').unwrap();
+                let digits = mantissa.replace('.', "");
+                let exponent: i32 = exponent.parse().unwrap();
+                let sign = if f < 0.0 { "-" } else { "" };
+                let written = if exponent < 0 {
+                    format!("0.{}{}", "0".repeat((-exponent - 1) as usize), digits)
+                } else if exponent as usize >= figs - 1 {
+                    format!("{}{}", digits, "0".repeat(exponent as usize - (figs - 1)))
+                } else {
+                    let (whole, fraction) = digits.split_at(exponent as usize + 1);
+                    format!("{}.{}", whole, fraction)
+                };
+                format!("{}{}", sign, written)
+            }
+        }
+    }
+
+    /// Multiplies the value by `factor`, keeping it an integer unless it gets too large for one.
+    pub(crate) fn scale(self, factor: i128) -> Value {
+        match self {
+            Value::Int(i) => match i.checked_mul(factor) {
+                Some(i) => Value::Int(i),
+                None => Value::Float(i as f64 * factor as f64),
+            },
+            Value::Float(f) => Value::Float(f * factor as f64),
+        }
+    }
+}
+
+/// replaces `-0` with `0`, so results like `0.0 * -1` aren't shown as `-0`
+pub(crate) fn positive_zero(n: f64) -> f64 {
+    if n == 0.0 {
+        0.0
+    } else {
+        n
+    }
+}
+
+impl Neg for Value {
+    type Output = Value;
+
+    fn neg(self) -> Value {
+        match self {
+            Value::Int(i) => match i.checked_neg() {
+                Some(i) => Value::Int(i),
+                None => Value::Float(-(i as f64)),
+            },
+            Value::Float(f) => Value::Float(-f),
+        }
+    }
+}
+
+/// Integers are written without a decimal point, and floats that are whole numbers with one (i.e. `4` and `4.0`).
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(n) if n.is_finite() && math::trunc(*n) == *n => write!(f, "{}.0", n),
+            Value::Float(n) => write!(f, "{}", n),
+        }
+    }
+}